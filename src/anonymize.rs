@@ -0,0 +1,155 @@
+use super::Configuration;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Hexadecimal characters of the hash kept in place of each ID
+static HASH_LENGTH: usize = 16;
+
+/// Replaces user and bot IDs with a salted hash, so that the same ID always maps to the same value
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    salt: Option<String>,
+}
+
+impl Anonymizer {
+    /// Replace every user and bot ID in a message (and in any replies nested under it)
+    pub fn apply(&self, message: &mut serde_json::Value) {
+        let salt = match &self.salt {
+            Some(salt) => salt,
+            None => return,
+        };
+
+        let fields = match message.as_object_mut() {
+            Some(fields) => fields,
+            None => return,
+        };
+
+        for key in ["user", "bot_id", "parent_user_id"] {
+            if let Some(id) = fields.get_mut(key) {
+                hash_in_place(salt, id);
+            }
+        }
+
+        if let Some(edited) = fields.get_mut("edited") {
+            if let Some(id) = edited.get_mut("user") {
+                hash_in_place(salt, id);
+            }
+        }
+
+        let reply_users = fields
+            .get_mut("reply_users")
+            .and_then(serde_json::Value::as_array_mut);
+
+        for id in reply_users.into_iter().flatten() {
+            hash_in_place(salt, id);
+        }
+
+        let reactions = fields
+            .get_mut("reactions")
+            .and_then(serde_json::Value::as_array_mut);
+
+        for reaction in reactions.into_iter().flatten() {
+            let users = reaction
+                .get_mut("users")
+                .and_then(serde_json::Value::as_array_mut);
+
+            for id in users.into_iter().flatten() {
+                hash_in_place(salt, id);
+            }
+        }
+
+        let thread = fields
+            .get_mut("thread")
+            .and_then(serde_json::Value::as_array_mut);
+
+        for reply in thread.into_iter().flatten() {
+            self.apply(reply);
+        }
+    }
+}
+
+impl From<&Configuration> for Anonymizer {
+    fn from(configuration: &Configuration) -> Self {
+        Self {
+            salt: configuration.anonymize_users.clone(),
+        }
+    }
+}
+
+/// Replace a string ID with its hash, leaving any other value alone
+fn hash_in_place(salt: &str, id: &mut serde_json::Value) {
+    if let Some(hashed) = id.as_str().map(|id| hash(salt, id)) {
+        *id = hashed.into();
+    }
+}
+
+/// Hash an ID with a salt, keeping the start of the hexadecimal SHA-256 digest
+fn hash(salt: &str, id: &str) -> String {
+    let digest = Sha256::new().chain_update(salt).chain_update(id).finalize();
+
+    let mut hashed = String::with_capacity(HASH_LENGTH);
+
+    for byte in &digest[..HASH_LENGTH / 2] {
+        let _ = write!(hashed, "{:02x}", byte);
+    }
+
+    hashed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// An anonymizer hashing with the provided salt
+    fn with_salt(salt: &str) -> Anonymizer {
+        Anonymizer {
+            salt: Some(salt.into()),
+        }
+    }
+
+    #[test]
+    fn maps_the_same_id_to_the_same_hash() {
+        let anonymizer = with_salt("pepper");
+        let mut parent = json!({
+            "user": "U0123",
+            "reply_users": ["U0123", "U0456"],
+            "reactions": [{ "name": "eyes", "users": ["U0456", "U0123"] }],
+            "thread": [{ "user": "U0123", "parent_user_id": "U0123" }],
+        });
+        let mut other = json!({ "user": "U0123", "edited": { "user": "U0123" } });
+
+        anonymizer.apply(&mut parent);
+        anonymizer.apply(&mut other);
+
+        // sha256("pepper" + "U0123"), truncated
+        let hashed = "ec87c2e41c3d466e";
+
+        assert_eq!(parent["user"], hashed);
+        assert_eq!(parent["reply_users"][0], hashed);
+        assert_eq!(parent["reactions"][0]["users"][1], hashed);
+        assert_eq!(parent["thread"][0]["user"], hashed);
+        assert_eq!(parent["thread"][0]["parent_user_id"], hashed);
+        assert_eq!(other["user"], hashed);
+        assert_eq!(other["edited"]["user"], hashed);
+
+        // other IDs get hashes of their own
+        assert_eq!(parent["reply_users"][1], parent["reactions"][0]["users"][0]);
+        assert_ne!(parent["reply_users"][1], hashed);
+    }
+
+    #[test]
+    fn hashes_differently_with_another_salt() {
+        assert_ne!(hash("pepper", "U0123"), hash("salt", "U0123"));
+        assert_eq!(hash("salt", "U0123").len(), HASH_LENGTH);
+    }
+
+    #[test]
+    fn leaves_messages_alone_without_a_salt() {
+        let mut message = json!({ "user": "U0123", "bot_id": "B0123" });
+
+        Anonymizer::default().apply(&mut message);
+
+        assert_eq!(message, json!({ "user": "U0123", "bot_id": "B0123" }));
+    }
+}
@@ -0,0 +1,260 @@
+use serde_json::Value;
+
+/// Fill in the `text` of a message from its Block Kit `blocks` when Slack left it empty (and do
+/// the same for any replies nested under it)
+///
+/// Apps often only describe their messages in blocks, so this recovers plain text from the usual
+/// block and element types, skipping the ones without any text (e.g. `divider` or `actions`).
+pub fn flatten_blocks(message: &mut Value) {
+    let fields = match message.as_object_mut() {
+        Some(fields) => fields,
+        None => return,
+    };
+
+    let has_text = fields
+        .get("text")
+        .and_then(Value::as_str)
+        .is_some_and(|text| !text.trim().is_empty());
+
+    if !has_text {
+        let text = fields
+            .get("blocks")
+            .and_then(Value::as_array)
+            .map(|blocks| blocks_text(blocks))
+            .unwrap_or_default();
+
+        if !text.is_empty() {
+            fields.insert("text".into(), text.into());
+        }
+    }
+
+    let thread = fields.get_mut("thread").and_then(Value::as_array_mut);
+
+    for reply in thread.into_iter().flatten() {
+        flatten_blocks(reply);
+    }
+}
+
+/// Extract the text of a list of blocks, one block per line
+fn blocks_text(blocks: &[Value]) -> String {
+    blocks
+        .iter()
+        .filter_map(block_text)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Extract the text of a single block, if it's a type that has any
+fn block_text(block: &Value) -> Option<String> {
+    match block.get("type")?.as_str()? {
+        "rich_text" => Some(
+            elements(block)
+                .filter_map(rich_text_element)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        "section" => {
+            let text = block.get("text").and_then(text_object);
+            let fields = block
+                .get("fields")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(text_object);
+
+            Some(
+                text.into_iter()
+                    .chain(fields)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+        }
+        "header" => block.get("text").and_then(text_object),
+        "context" => Some(
+            elements(block)
+                .filter_map(text_object)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => None,
+    }
+}
+
+/// Extract the text of a top-level element of a `rich_text` block
+fn rich_text_element(element: &Value) -> Option<String> {
+    match element.get("type")?.as_str()? {
+        "rich_text_section" => Some(inline_text(element)),
+        "rich_text_preformatted" => Some(format!("```{}```", inline_text(element))),
+        "rich_text_quote" => Some(
+            inline_text(element)
+                .lines()
+                .map(|line| format!("> {}", line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        "rich_text_list" => {
+            let is_ordered = element.get("style").and_then(Value::as_str) == Some("ordered");
+            let depth = element.get("indent").and_then(Value::as_u64).unwrap_or(0);
+            let indent = "  ".repeat(depth as usize);
+
+            Some(
+                elements(element)
+                    .enumerate()
+                    .map(|(index, item)| {
+                        if is_ordered {
+                            format!("{}{}. {}", indent, index + 1, inline_text(item))
+                        } else {
+                            format!("{}• {}", indent, inline_text(item))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Join the inline elements (text, links, mentions and so on) of a rich text element, using the
+/// same markup as Slack's own `text` for mentions
+fn inline_text(element: &Value) -> String {
+    elements(element)
+        .filter_map(|inline| {
+            let field = |key| inline.get(key).and_then(Value::as_str);
+
+            match field("type")? {
+                "text" => field("text").map(Into::into),
+                "link" => field("text").or(field("url")).map(Into::into),
+                "user" => field("user_id").map(|id| format!("<@{}>", id)),
+                "channel" => field("channel_id").map(|id| format!("<#{}>", id)),
+                "usergroup" => field("usergroup_id").map(|id| format!("<!subteam^{}>", id)),
+                "broadcast" => field("range").map(|range| format!("<!{}>", range)),
+                "emoji" => field("name").map(|name| format!(":{}:", name)),
+                "date" => field("fallback").map(Into::into),
+                "color" => field("value").map(Into::into),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The text of a `plain_text` or `mrkdwn` text object
+fn text_object(object: &Value) -> Option<String> {
+    match object.get("type")?.as_str()? {
+        "plain_text" | "mrkdwn" => object.get("text")?.as_str().map(Into::into),
+        _ => None,
+    }
+}
+
+/// The nested `elements` of a block or element, if it has any
+fn elements(value: &Value) -> impl Iterator<Item = &Value> {
+    value
+        .get("elements")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The `text` filled in for a message with these blocks and an empty `text`
+    fn flattened(blocks: Value) -> Value {
+        let mut message = json!({ "type": "message", "text": "", "blocks": blocks });
+        flatten_blocks(&mut message);
+
+        message["text"].clone()
+    }
+
+    #[test]
+    fn flattens_rich_text_sections() {
+        let blocks = json!([{
+            "type": "rich_text",
+            "elements": [{
+                "type": "rich_text_section",
+                "elements": [
+                    { "type": "text", "text": "Hi " },
+                    { "type": "user", "user_id": "U0123" },
+                    { "type": "text", "text": ", see " },
+                    { "type": "link", "url": "https://example.com", "text": "the docs" },
+                    { "type": "text", "text": " in " },
+                    { "type": "channel", "channel_id": "C0123" },
+                    { "type": "text", "text": " " },
+                    { "type": "emoji", "name": "wave" },
+                    { "type": "broadcast", "range": "here" },
+                ],
+            }],
+        }]);
+
+        assert_eq!(
+            flattened(blocks),
+            "Hi <@U0123>, see the docs in <#C0123> :wave:<!here>"
+        );
+    }
+
+    #[test]
+    fn flattens_rich_text_lists_quotes_and_code() {
+        let text = |text| json!({ "type": "rich_text_section", "elements": [{ "type": "text", "text": text }] });
+        let blocks = json!([{
+            "type": "rich_text",
+            "elements": [
+                {
+                    "type": "rich_text_list",
+                    "style": "ordered",
+                    "elements": [text("first"), text("second")],
+                },
+                {
+                    "type": "rich_text_list",
+                    "style": "bullet",
+                    "indent": 1,
+                    "elements": [text("nested")],
+                },
+                {
+                    "type": "rich_text_quote",
+                    "elements": [{ "type": "text", "text": "quoted\nover two lines" }],
+                },
+                {
+                    "type": "rich_text_preformatted",
+                    "elements": [{ "type": "text", "text": "cargo test" }],
+                },
+            ],
+        }]);
+
+        assert_eq!(
+            flattened(blocks),
+            "1. first\n2. second\n  • nested\n> quoted\n> over two lines\n```cargo test```"
+        );
+    }
+
+    #[test]
+    fn skips_blocks_without_text() {
+        let blocks = json!([
+            { "type": "header", "text": { "type": "plain_text", "text": "Deploy" } },
+            { "type": "divider" },
+            { "type": "actions", "elements": [{ "type": "button" }] },
+            { "type": "section", "text": { "type": "mrkdwn", "text": "*done*" } },
+            { "type": "some_future_block", "text": "ignored" },
+        ]);
+
+        assert_eq!(flattened(blocks), "Deploy\n*done*");
+    }
+
+    #[test]
+    fn keeps_existing_text_and_flattens_nested_replies() {
+        let blocks =
+            json!([{ "type": "section", "text": { "type": "plain_text", "text": "from blocks" } }]);
+        let mut message = json!({
+            "text": "already here",
+            "blocks": blocks,
+            "thread": [{ "text": " ", "blocks": blocks }],
+        });
+
+        flatten_blocks(&mut message);
+
+        assert_eq!(message["text"], "already here");
+        assert_eq!(message["thread"][0]["text"], "from blocks");
+    }
+}
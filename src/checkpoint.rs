@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufReader, ErrorKind},
+    path::Path,
+};
+
+/// Progress through a channel's history, saved so that an interrupted export can resume
+///
+/// The cursor is only kept at page boundaries, where it points at exactly the messages still to
+/// come. The timestamp is a fallback for when Slack rejects the cursor (e.g. once it expires):
+/// since Slack returns the newest messages first, every message older than it is still to come.
+/// The output's length lets a resumed export continue the same file, dropping anything written
+/// after the page boundary (e.g. the closing bracket, or half a message).
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Checkpoint {
+    /// Cursor to the next page of the history
+    pub cursor: Option<String>,
+    /// `ts` of the last top-level message already exported
+    pub last_ts: Option<String>,
+    /// Length of the output file once the page was written, where a resumed export continues it
+    pub output_len: Option<u64>,
+}
+
+/// What to do with a checkpoint left by a previous run
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckpointPolicy {
+    /// Continue from the checkpoint if there is one
+    #[default]
+    Resume,
+    /// Delete the checkpoint and export the whole history again
+    Restart,
+}
+
+impl CheckpointPolicy {
+    /// Read the checkpoint at a path according to the policy, deleting it when restarting
+    pub fn load(self, path: &Path) -> anyhow::Result<Option<Checkpoint>> {
+        match self {
+            Self::Resume => Checkpoint::read(path),
+            Self::Restart => match fs::remove_file(path) {
+                Ok(()) => {
+                    tracing::info!(
+                        "Deleted checkpoint {} to restart the export",
+                        path.display()
+                    );
+                    Ok(None)
+                }
+                Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+                Err(error) => anyhow::bail!("Error deleting {}: {}", path.display(), error),
+            },
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Read a checkpoint left by a previous run, if there is one
+    pub fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                anyhow::bail!("Error opening {}: {}", path.display(), error)
+            }
+        };
+
+        let checkpoint = serde_json::from_reader(BufReader::new(file)).map_err(|error| {
+            anyhow::anyhow!(
+                "Error reading {} as a checkpoint: {}",
+                path.display(),
+                error
+            )
+        })?;
+
+        Ok(Some(checkpoint))
+    }
+
+    /// Replace the checkpoint at a path, without leaving a partial file behind if interrupted
+    ///
+    /// The new checkpoint is written to a temporary file alongside it (so that it can be renamed
+    /// into place), which is deleted if writing it fails.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let directory = match path.parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => directory,
+            _ => Path::new("."),
+        };
+
+        let mut partial = tempfile::Builder::new()
+            .prefix(".slump-checkpoint-")
+            .suffix(".partial")
+            .tempfile_in(directory)?;
+
+        serde_json::to_writer(partial.as_file_mut(), self)?;
+        partial.persist(path).map_err(|error| error.error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A checkpoint between two pages of the history
+    fn between_pages() -> Checkpoint {
+        Checkpoint {
+            cursor: Some("bmV4dF90czox+/NTEyMDA=".into()),
+            last_ts: Some("1700000001.000100".into()),
+            output_len: Some(4096),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_its_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+
+        between_pages().write(&path).unwrap();
+        assert_eq!(Checkpoint::read(&path).unwrap(), Some(between_pages()));
+
+        // a later checkpoint replaces the earlier one, without leaving partial files behind
+        let finished_page = Checkpoint {
+            cursor: None,
+            ..between_pages()
+        };
+        finished_page.write(&path).unwrap();
+
+        assert_eq!(Checkpoint::read(&path).unwrap(), Some(finished_page));
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn reads_checkpoints_without_an_output_length() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        fs::write(&path, r#"{"cursor":"page-2","last_ts":"1.000000"}"#).unwrap();
+
+        let checkpoint = Checkpoint::read(&path).unwrap().unwrap();
+
+        assert_eq!(checkpoint.cursor.as_deref(), Some("page-2"));
+        assert_eq!(checkpoint.output_len, None);
+    }
+
+    #[test]
+    fn reads_nothing_without_a_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+
+        assert_eq!(Checkpoint::read(&path).unwrap(), None);
+        assert_eq!(CheckpointPolicy::Restart.load(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_checkpoints() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        fs::write(&path, "{\"cursor\":").unwrap();
+
+        let error = Checkpoint::read(&path).unwrap_err().to_string();
+
+        assert!(error.contains("as a checkpoint"), "{}", error);
+    }
+
+    #[test]
+    fn deletes_the_checkpoint_to_restart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        between_pages().write(&path).unwrap();
+
+        assert_eq!(
+            CheckpointPolicy::Resume.load(&path).unwrap(),
+            Some(between_pages())
+        );
+        assert_eq!(CheckpointPolicy::Restart.load(&path).unwrap(), None);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,344 @@
+use super::{dates, output::Sink};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    path::PathBuf,
+};
+use tempfile::NamedTempFile;
+
+/// Position of a message in the chronological output: the `ts` of the top-level message it
+/// belongs to, then its own `ts` (with seconds and microseconds compared as numbers)
+type Key = ((i64, u32), (i64, u32));
+
+/// Reorders messages oldest first before writing them to another sink, with bounded memory
+///
+/// Messages are buffered until the threshold is reached, then sorted and spilled to a temporary
+/// file as a run. Once every message has been written, the runs are merged into the sink. Thread
+/// replies following their parent stay after it, since they're ordered by the parent's `ts` first.
+/// Each run is deleted as soon as the sorter is dropped, whether or not it finished.
+pub struct ChronologicalSorter<S> {
+    out: S,
+    threshold: usize,
+    buffer: Vec<(Key, serde_json::Value)>,
+    runs: Vec<NamedTempFile>,
+    thread_ts: Option<String>,
+    directory: Option<PathBuf>,
+}
+
+impl<S: Sink> ChronologicalSorter<S> {
+    /// Create a sorter writing to a sink, holding at most `threshold` messages in memory
+    pub fn new(out: S, threshold: usize) -> Self {
+        Self {
+            out,
+            threshold: threshold.max(1),
+            buffer: Vec::new(),
+            runs: Vec::new(),
+            thread_ts: None,
+            directory: None,
+        }
+    }
+
+    /// Spill runs to a directory other than the system's temporary directory (e.g. one on a
+    /// larger disk)
+    pub fn in_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Sort the buffered messages and write them to a new temporary file
+    fn spill(&mut self) -> anyhow::Result<()> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("slump-").suffix(".ndjson");
+
+        let run = match &self.directory {
+            Some(directory) => builder.tempfile_in(directory)?,
+            None => builder.tempfile()?,
+        };
+        let path = run.path().to_owned();
+        let mut file = BufWriter::new(run.reopen()?);
+
+        // track the file before writing it, so that it's removed even if writing fails
+        self.runs.push(run);
+        self.buffer.sort_by_key(|(key, _)| *key);
+
+        for entry in self.buffer.drain(..) {
+            serde_json::to_writer(&mut file, &entry)?;
+            file.write_all(b"\n")?;
+        }
+
+        file.flush()?;
+
+        tracing::debug!(path = %path.display(), "Spilled a sorted run of messages");
+
+        Ok(())
+    }
+
+    /// Merge the spilled runs and the buffered messages into the sink, oldest first
+    fn merge(&mut self) -> anyhow::Result<()> {
+        self.buffer.sort_by_key(|(key, _)| *key);
+
+        let mut runs = self
+            .runs
+            .iter()
+            .map(|run| Ok(BufReader::new(run.reopen()?).lines()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut buffer = self.buffer.drain(..);
+
+        // ties between sources go to the one written first, keeping equal keys in arrival order
+        let mut heads = BinaryHeap::new();
+
+        for (source, run) in runs.iter_mut().enumerate() {
+            if let Some((key, message)) = next_entry(run)? {
+                heads.push(Reverse(Head {
+                    key,
+                    source,
+                    message,
+                }));
+            }
+        }
+
+        let buffer_source = runs.len();
+
+        if let Some((key, message)) = buffer.next() {
+            heads.push(Reverse(Head {
+                key,
+                source: buffer_source,
+                message,
+            }));
+        }
+
+        while let Some(Reverse(head)) = heads.pop() {
+            self.out.write(&head.message)?;
+
+            let next = match runs.get_mut(head.source) {
+                Some(run) => next_entry(run)?,
+                None => buffer.next(),
+            };
+
+            if let Some((key, message)) = next {
+                heads.push(Reverse(Head {
+                    key,
+                    source: head.source,
+                    message,
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove every spilled run
+    fn clean_up(&mut self) {
+        for run in self.runs.drain(..) {
+            let path = run.path().to_owned();
+
+            if let Err(error) = run.close() {
+                tracing::warn!(
+                    "Couldn't remove temporary file {}: {}",
+                    path.display(),
+                    error
+                );
+            }
+        }
+    }
+}
+
+impl<S: Sink> Sink for ChronologicalSorter<S> {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        let ts = message.get("ts").and_then(serde_json::Value::as_str);
+        let thread_ts = message.get("thread_ts").and_then(serde_json::Value::as_str);
+
+        // replies directly following their parent are placed after it, like ThreadSplitter
+        let top_level_ts = match thread_ts {
+            Some(thread_ts)
+                if ts != Some(thread_ts) && self.thread_ts.as_deref() == Some(thread_ts) =>
+            {
+                thread_ts
+            }
+            _ => {
+                self.thread_ts = thread_ts
+                    .filter(|thread_ts| ts == Some(*thread_ts))
+                    .map(String::from);
+                ts.unwrap_or_default()
+            }
+        };
+
+        let key = (parse_ts(top_level_ts), parse_ts(ts.unwrap_or_default()));
+        self.buffer.push((key, message.clone()));
+
+        if self.buffer.len() >= self.threshold {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        // nothing can be written before the oldest message is known
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let merged = self.merge();
+        self.clean_up();
+        merged?;
+
+        self.out.finish()
+    }
+}
+
+/// The next message of a sorted run, along with its key
+struct Head {
+    key: Key,
+    source: usize,
+    message: serde_json::Value,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        (self.key, self.source) == (other.key, other.source)
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.key, self.source).cmp(&(other.key, other.source))
+    }
+}
+
+/// Read the next message (and its key) from a spilled run
+fn next_entry(
+    run: &mut Lines<BufReader<File>>,
+) -> anyhow::Result<Option<(Key, serde_json::Value)>> {
+    match run.next() {
+        Some(line) => Ok(Some(serde_json::from_str(&line?)?)),
+        None => Ok(None),
+    }
+}
+
+/// Split a Slack timestamp into comparable seconds and microseconds, placing malformed ones first
+fn parse_ts(ts: &str) -> (i64, u32) {
+    dates::from_slack_timestamp(ts)
+        .map(|datetime| (datetime.timestamp(), datetime.timestamp_subsec_micros()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Sink that keeps every message it's given
+    #[derive(Default)]
+    struct Collected(Vec<serde_json::Value>);
+
+    impl Sink for Collected {
+        fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+            self.0.push(message.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn finish(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Write messages to a sorter spilling every message, returning the paths of its runs
+    fn spill_every_message(sorter: &mut ChronologicalSorter<Collected>) -> Vec<std::path::PathBuf> {
+        for ts in ["3.000000", "1.000000", "2.000000"] {
+            sorter.write(&json!({ "ts": ts })).unwrap();
+        }
+
+        let paths: Vec<_> = sorter
+            .runs
+            .iter()
+            .map(|run| run.path().to_owned())
+            .collect();
+        assert_eq!(paths.len(), 3);
+        assert!(paths.iter().all(|path| path.exists()));
+
+        paths
+    }
+
+    #[test]
+    fn compares_timestamps_as_numbers() {
+        // seconds of different lengths, and microseconds that only differ as numbers
+        assert!(parse_ts("999999999.000000") < parse_ts("1000000000.000000"));
+        assert!(parse_ts("1700000000.000009") < parse_ts("1700000000.000010"));
+        assert_eq!(parse_ts("1700000000.000100"), (1700000000, 100));
+
+        // malformed timestamps are placed first rather than failing the sort
+        assert_eq!(parse_ts("not a ts"), (0, 0));
+        assert!(parse_ts("") < parse_ts("1.000000"));
+    }
+
+    #[test]
+    fn sorts_oldest_first_keeping_replies_after_their_parent() {
+        let messages = [
+            json!({ "ts": "1000000000.000000" }),
+            json!({ "ts": "999999999.000000", "thread_ts": "999999999.000000" }),
+            json!({ "ts": "1000000001.000000", "thread_ts": "999999999.000000" }),
+            json!({ "ts": "999999998.000000" }),
+        ];
+
+        // spilling some of the messages mustn't change the order
+        for threshold in [1, 2, 100] {
+            let mut sorter = ChronologicalSorter::new(Collected::default(), threshold);
+
+            for message in &messages {
+                sorter.write(message).unwrap();
+            }
+
+            sorter.finish().unwrap();
+
+            let ts: Vec<_> = sorter.out.0.iter().map(|message| &message["ts"]).collect();
+            assert_eq!(
+                ts,
+                [
+                    "999999998.000000",
+                    "999999999.000000",
+                    "1000000001.000000",
+                    "1000000000.000000",
+                ],
+                "{}",
+                threshold
+            );
+        }
+    }
+
+    #[test]
+    fn removes_its_runs_when_dropped_unfinished() {
+        let mut sorter = ChronologicalSorter::new(Collected::default(), 1);
+        let paths = spill_every_message(&mut sorter);
+
+        // a cancelled or failed export drops the sorter without finishing it
+        drop(sorter);
+
+        assert!(paths.iter().all(|path| !path.exists()), "{:?}", paths);
+    }
+
+    #[test]
+    fn removes_its_runs_once_finished() {
+        let mut sorter = ChronologicalSorter::new(Collected::default(), 1);
+        let paths = spill_every_message(&mut sorter);
+
+        sorter.finish().unwrap();
+
+        assert!(paths.iter().all(|path| !path.exists()), "{:?}", paths);
+        assert_eq!(sorter.out.0.len(), 3);
+    }
+}
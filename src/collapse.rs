@@ -0,0 +1,154 @@
+use super::{dates, Configuration};
+use chrono::{DateTime, Duration, Utc};
+
+/// Merges consecutive messages from the same user into a single record, for readable transcripts
+#[derive(Debug, Default)]
+pub struct Collapser {
+    window: Option<Duration>,
+    pending: Option<Group>,
+}
+
+impl Collapser {
+    /// Add a message, returning the previous record once a message can't be merged into it
+    pub fn push(&mut self, message: serde_json::Value) -> Option<serde_json::Value> {
+        let window = match self.window {
+            Some(window) => window,
+            None => return Some(message),
+        };
+
+        let message = match &mut self.pending {
+            Some(group) => match group.join(message, window) {
+                Ok(()) => return None,
+                Err(message) => message,
+            },
+            None => message,
+        };
+
+        self.pending
+            .replace(Group::new(message))
+            .map(Group::into_message)
+    }
+
+    /// Return the last record once every message has been added
+    pub fn finish(&mut self) -> Option<serde_json::Value> {
+        self.pending.take().map(Group::into_message)
+    }
+}
+
+impl From<&Configuration> for Collapser {
+    fn from(configuration: &Configuration) -> Self {
+        Self {
+            window: configuration
+                .collapse_consecutive
+                .map(|seconds| Duration::seconds(seconds as i64)),
+            pending: None,
+        }
+    }
+}
+
+/// Consecutive messages merged into a record based on the earliest of them
+#[derive(Debug)]
+struct Group {
+    message: serde_json::Value,
+    key: Option<(String, Option<String>)>,
+    texts: Vec<String>,
+    ts_list: Vec<String>,
+    oldest: Option<DateTime<Utc>>,
+    newest: Option<DateTime<Utc>>,
+}
+
+impl Group {
+    /// Start a record with a single message
+    fn new(message: serde_json::Value) -> Self {
+        let ts = string_field(&message, "ts");
+        let datetime = ts.as_deref().and_then(dates::from_slack_timestamp);
+
+        Self {
+            key: key(&message),
+            texts: vec![string_field(&message, "text").unwrap_or_default()],
+            ts_list: ts.into_iter().collect(),
+            oldest: datetime,
+            newest: datetime,
+            message,
+        }
+    }
+
+    /// Merge a message into the record if it's from the same user, in the same place and close
+    /// enough in time to either end of the record, handing it back otherwise
+    fn join(
+        &mut self,
+        message: serde_json::Value,
+        window: Duration,
+    ) -> Result<(), serde_json::Value> {
+        let (oldest, newest) = match (self.oldest, self.newest) {
+            (Some(oldest), Some(newest)) => (oldest, newest),
+            _ => return Err(message),
+        };
+        let ts = match string_field(&message, "ts") {
+            Some(ts) => ts,
+            None => return Err(message),
+        };
+        let datetime = match dates::from_slack_timestamp(&ts) {
+            Some(datetime) => datetime,
+            None => return Err(message),
+        };
+
+        if self.key.is_none() || key(&message) != self.key {
+            return Err(message);
+        }
+
+        let text = string_field(&message, "text").unwrap_or_default();
+
+        // messages usually arrive newest first, but thread replies arrive oldest first
+        if datetime < oldest && oldest - datetime <= window {
+            self.texts.insert(0, text);
+            self.ts_list.insert(0, ts);
+            self.oldest = Some(datetime);
+            self.message = message;
+        } else if datetime >= newest && datetime - newest <= window {
+            self.texts.push(text);
+            self.ts_list.push(ts);
+            self.newest = Some(datetime);
+        } else {
+            return Err(message);
+        }
+
+        Ok(())
+    }
+
+    /// The merged record, or the original message if nothing was merged into it
+    fn into_message(mut self) -> serde_json::Value {
+        if self.ts_list.len() > 1 {
+            if let Some(fields) = self.message.as_object_mut() {
+                fields.insert("text".into(), self.texts.join("\n").into());
+                fields.insert("ts_list".into(), self.ts_list.into());
+            }
+        }
+
+        self.message
+    }
+}
+
+/// The user and thread (for replies) that a message has to share with others to be merged,
+/// or nothing for messages that are never merged (bot and system messages or thread parents)
+fn key(message: &serde_json::Value) -> Option<(String, Option<String>)> {
+    let user = string_field(message, "user")?;
+    let ts = string_field(message, "ts")?;
+    let thread_ts = string_field(message, "thread_ts");
+
+    let is_bot = message.get("bot_id").is_some();
+
+    if is_bot || message.get("subtype").is_some() || thread_ts.as_deref() == Some(ts.as_str()) {
+        return None;
+    }
+
+    Some((user, thread_ts))
+}
+
+/// A string field of a message
+fn string_field(message: &serde_json::Value, key: &str) -> Option<String> {
+    message
+        .get(key)
+        .and_then(serde_json::Value::as_str)
+        .map(String::from)
+}
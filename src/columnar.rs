@@ -0,0 +1,109 @@
+use super::Sink;
+use arrow_array::{
+    builder::{Int64Builder, StringBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{io::Write, iter, sync::Arc};
+
+/// Number of messages buffered in memory before being written out as a Parquet row group
+static ROW_GROUP_SIZE: usize = 10_000;
+
+/// Top-level message fields flattened into string columns
+static STRING_COLUMNS: [&str; 6] = ["ts", "user", "type", "subtype", "text", "thread_ts"];
+
+/// Writes messages as rows of an Apache Parquet file, one column per common message field
+///
+/// Rows are buffered in memory until a full row group of messages has been collected, so memory
+/// use is bounded by the size of a single row group rather than the whole export. The file's
+/// footer is written once the export finishes, so the output is only readable after that.
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    strings: Vec<StringBuilder>,
+    reply_count: Int64Builder,
+    rows: usize,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    /// Create a Parquet writer with the flattened message schema
+    pub fn new(out: W) -> anyhow::Result<Self> {
+        let fields = STRING_COLUMNS
+            .iter()
+            .map(|name| Field::new(*name, DataType::Utf8, true))
+            .chain(iter::once(Field::new("reply_count", DataType::Int64, true)));
+        let schema = Arc::new(Schema::new(fields.collect::<Vec<_>>()));
+
+        let properties = WriterProperties::builder()
+            .set_max_row_group_row_count(Some(ROW_GROUP_SIZE))
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(out, schema.clone(), Some(properties))?;
+
+        Ok(Self {
+            writer,
+            schema,
+            strings: STRING_COLUMNS
+                .iter()
+                .map(|_| StringBuilder::new())
+                .collect(),
+            reply_count: Int64Builder::new(),
+            rows: 0,
+        })
+    }
+
+    /// Write the buffered rows out as a single row group
+    fn write_row_group(&mut self) -> anyhow::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let columns: Vec<ArrayRef> = self
+            .strings
+            .iter_mut()
+            .map(|column| Arc::new(column.finish()) as ArrayRef)
+            .chain(iter::once(Arc::new(self.reply_count.finish()) as ArrayRef))
+            .collect();
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        self.rows = 0;
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> Sink for ParquetWriter<W> {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        for (name, column) in STRING_COLUMNS.iter().zip(&mut self.strings) {
+            column.append_option(message.get(*name).and_then(serde_json::Value::as_str));
+        }
+
+        self.reply_count.append_option(
+            message
+                .get("reply_count")
+                .and_then(serde_json::Value::as_i64),
+        );
+        self.rows += 1;
+
+        if self.rows >= ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        // row groups are only written once full, so that they stay a useful size
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.write_row_group()?;
+        self.writer.finish()?;
+
+        Ok(())
+    }
+}
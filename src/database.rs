@@ -0,0 +1,101 @@
+use super::Sink;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+/// Number of messages inserted in each transaction
+static TRANSACTION_SIZE: usize = 1_000;
+
+/// Top-level message fields copied into their own columns, besides the full message
+static COLUMNS: [&str; 6] = ["ts", "user", "type", "subtype", "thread_ts", "text"];
+
+/// Statements that create the `messages` table and its index, unless they already exist
+static SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS messages (
+        ts TEXT,
+        user TEXT,
+        type TEXT,
+        subtype TEXT,
+        thread_ts TEXT,
+        text TEXT,
+        raw_json TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS messages_ts ON messages (ts);
+";
+
+/// Statement that inserts a single message
+static INSERT: &str = "
+    INSERT INTO messages (ts, user, type, subtype, thread_ts, text, raw_json)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+";
+
+/// Inserts messages as rows of a `messages` table in an SQLite database, keeping each message's
+/// full JSON alongside the columns for its common fields
+///
+/// Messages are inserted in transactions of a fixed size rather than one at a time, which is far
+/// faster. Each transaction is committed once it's full, when the output is flushed and once the
+/// export finishes, so an interrupted export keeps every message up to the last commit.
+pub struct SqliteWriter {
+    connection: Connection,
+    rows: usize,
+}
+
+impl SqliteWriter {
+    /// Open (or create) the database at a path, creating the `messages` table if it's missing
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            connection,
+            rows: 0,
+        })
+    }
+
+    /// Commit the open transaction, if any messages were inserted since the last one
+    fn commit(&mut self) -> anyhow::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        self.connection.execute_batch("COMMIT")?;
+        self.rows = 0;
+
+        Ok(())
+    }
+}
+
+impl Sink for SqliteWriter {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        if self.rows == 0 {
+            self.connection.execute_batch("BEGIN")?;
+        }
+
+        let [ts, user, kind, subtype, thread_ts, text] =
+            COLUMNS.map(|name| message.get(name).and_then(serde_json::Value::as_str));
+
+        self.connection.prepare_cached(INSERT)?.execute(params![
+            ts,
+            user,
+            kind,
+            subtype,
+            thread_ts,
+            text,
+            message.to_string()
+        ])?;
+        self.rows += 1;
+
+        if self.rows >= TRANSACTION_SIZE {
+            self.commit()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.commit()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.commit()
+    }
+}
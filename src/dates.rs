@@ -0,0 +1,287 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Timezone used for dates without an explicit offset when none is configured
+static DEFAULT_TIMEZONE: Tz = Tz::UTC;
+
+/// Resolve an optional timezone name (e.g. `America/New_York`) into a timezone
+pub fn timezone(name: Option<&str>) -> anyhow::Result<Tz> {
+    match name {
+        Some(name) => name.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid timezone: {} (expected an IANA timezone name, e.g. America/New_York)",
+                name
+            )
+        }),
+        None => Ok(DEFAULT_TIMEZONE),
+    }
+}
+
+/// Convert a human-readable date into a Slack timestamp (seconds.microseconds since the epoch)
+///
+/// Accepts RFC 3339 timestamps with an explicit offset (`2021-03-14T02:30:00-05:00`),
+/// local date-times (`2021-03-14T02:30:00`) and bare dates (`2021-03-14`, meaning midnight).
+/// Local values are interpreted in the provided timezone, and local times that are skipped
+/// or repeated by a daylight saving transition are rejected rather than guessed at.
+pub fn to_slack_timestamp(date: &str, timezone: Tz) -> anyhow::Result<String> {
+    let date = date.trim();
+
+    // explicit offsets are unambiguous and ignore the configured timezone
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Ok(format_timestamp(
+            datetime.timestamp(),
+            datetime.timestamp_subsec_micros(),
+        ));
+    }
+
+    let local = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|day| day.and_hms_opt(0, 0, 0).expect("midnight is always valid"))
+        })
+        .map_err(|_| anyhow::anyhow!("Unrecognized date format: {}", date))?;
+
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(datetime) => Ok(format_timestamp(
+            datetime.timestamp(),
+            datetime.timestamp_subsec_micros(),
+        )),
+        LocalResult::Ambiguous(earliest, latest) => Err(anyhow::anyhow!(
+            "Ambiguous date {} in {}: could be {} or {}. Include an explicit offset instead",
+            date,
+            timezone,
+            earliest.to_rfc3339(),
+            latest.to_rfc3339()
+        )),
+        LocalResult::None => Err(anyhow::anyhow!(
+            "Date {} does not exist in {} (skipped by a daylight saving transition)",
+            date,
+            timezone
+        )),
+    }
+}
+
+/// Convert a human-readable upper bound into a Slack timestamp, like [`to_slack_timestamp`]
+///
+/// Slack's `latest` bound is exclusive, so a bare date (`2021-03-14`) means the start of the next
+/// day, which includes every message sent on the date itself.
+pub fn to_latest_timestamp(date: &str, timezone: Tz) -> anyhow::Result<String> {
+    let day = match NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+        Ok(day) => day,
+        Err(_) => return to_slack_timestamp(date, timezone),
+    };
+
+    let next_day = day
+        .succ_opt()
+        .and_then(|next_day| start_of_day(next_day, timezone))
+        .ok_or_else(|| anyhow::anyhow!("Date out of range: {}", date))?;
+
+    Ok(format_timestamp(
+        next_day.timestamp(),
+        next_day.timestamp_subsec_micros(),
+    ))
+}
+
+/// Convert a Unix timestamp in seconds into a Slack timestamp, to the microsecond
+pub fn from_unix_timestamp(seconds: f64) -> String {
+    let microseconds = (seconds * 1_000_000.0).round() as i64;
+
+    format_timestamp(
+        microseconds.div_euclid(1_000_000),
+        microseconds.rem_euclid(1_000_000) as u32,
+    )
+}
+
+/// Parse a Slack timestamp (e.g. `1609459200.000100`) into a UTC date-time
+pub fn from_slack_timestamp(ts: &str) -> Option<DateTime<Utc>> {
+    let (seconds, microseconds) = ts.split_once('.').unwrap_or((ts, "0"));
+    let seconds = seconds.parse().ok()?;
+    let microseconds: u32 = microseconds.parse().ok()?;
+
+    DateTime::from_timestamp(seconds, microseconds.checked_mul(1000)?)
+}
+
+/// Split a range of Slack timestamps into one window per calendar day in a timezone, newest first
+///
+/// Slack treats both bounds of a request as exclusive, so each window starts a microsecond before
+/// midnight to include messages sent exactly at midnight, which the next window excludes.
+pub fn day_windows(
+    oldest: &str,
+    latest: Option<&str>,
+    timezone: Tz,
+) -> Option<Vec<(String, String)>> {
+    let start = from_slack_timestamp(oldest)?;
+    let mut end = match latest {
+        Some(latest) => from_slack_timestamp(latest)?,
+        None => Utc::now(),
+    };
+    let mut windows = Vec::new();
+
+    while end > start {
+        let day = (end - Duration::microseconds(1))
+            .with_timezone(&timezone)
+            .date_naive();
+        let midnight = start_of_day(day, timezone)?.max(start);
+
+        let window_oldest = if midnight == start {
+            oldest.to_owned()
+        } else {
+            let before_midnight = midnight - Duration::microseconds(1);
+
+            format_timestamp(
+                before_midnight.timestamp(),
+                before_midnight.timestamp_subsec_micros(),
+            )
+        };
+
+        windows.push((
+            window_oldest,
+            format_timestamp(end.timestamp(), end.timestamp_subsec_micros()),
+        ));
+        end = midnight;
+    }
+
+    Some(windows)
+}
+
+/// Find the first instant of a calendar day in a timezone
+fn start_of_day(day: NaiveDate, timezone: Tz) -> Option<DateTime<Utc>> {
+    // a few timezones skip midnight for daylight saving, starting the day an hour later
+    (0..2)
+        .filter_map(|hour| day.and_hms_opt(hour, 0, 0))
+        .find_map(|local| timezone.from_local_datetime(&local).earliest())
+        .map(|datetime| datetime.with_timezone(&Utc))
+}
+
+/// Format seconds and microseconds in the fixed-precision style Slack uses for `ts` values
+fn format_timestamp(seconds: i64, microseconds: u32) -> String {
+    format!("{}.{:06}", seconds, microseconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::New_York;
+
+    #[test]
+    fn bare_to_date_includes_the_whole_day() {
+        // 2021-03-15T00:00:00Z
+        assert_eq!(
+            to_latest_timestamp("2021-03-14", Tz::UTC).unwrap(),
+            "1615766400.000000"
+        );
+
+        // New York springs forward on 2021-03-14, but the next day still starts at midnight EDT
+        assert_eq!(
+            to_latest_timestamp("2021-03-14", New_York).unwrap(),
+            "1615780800.000000"
+        );
+    }
+
+    #[test]
+    fn to_date_with_a_time_is_kept_as_is() {
+        assert_eq!(
+            to_latest_timestamp("2021-03-14T12:00:00", Tz::UTC).unwrap(),
+            to_slack_timestamp("2021-03-14T12:00:00", Tz::UTC).unwrap()
+        );
+    }
+
+    #[test]
+    fn explicit_offsets_ignore_the_timezone() {
+        // 2021-03-14T07:30:00Z
+        assert_eq!(
+            to_slack_timestamp("2021-03-14T02:30:00-05:00", New_York).unwrap(),
+            "1615707000.000000"
+        );
+    }
+
+    #[test]
+    fn rejects_local_times_skipped_by_spring_forward() {
+        let error = to_slack_timestamp("2021-03-14T02:30:00", New_York).unwrap_err();
+
+        assert!(error.to_string().contains("does not exist"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_local_times_repeated_by_fall_back() {
+        let error = to_slack_timestamp("2021-11-07T01:30:00", New_York).unwrap_err();
+
+        assert!(error.to_string().starts_with("Ambiguous date"), "{}", error);
+    }
+
+    /// The length of each window, in seconds
+    fn window_lengths(windows: &[(String, String)]) -> Vec<i64> {
+        windows
+            .iter()
+            .map(|(oldest, latest)| {
+                let oldest = from_slack_timestamp(oldest).unwrap();
+                let latest = from_slack_timestamp(latest).unwrap();
+
+                (latest - oldest).num_seconds()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_days_across_spring_forward() {
+        // 2021-03-13T00:00:00-05:00 to 2021-03-15T00:00:00-04:00
+        let windows =
+            day_windows("1615611600.000000", Some("1615780800.000000"), New_York).unwrap();
+
+        assert_eq!(
+            windows,
+            [
+                (
+                    "1615697999.999999".to_owned(),
+                    "1615780800.000000".to_owned()
+                ),
+                (
+                    "1615611600.000000".to_owned(),
+                    "1615698000.000000".to_owned()
+                ),
+            ]
+        );
+        // the day that springs forward only has 23 hours
+        assert_eq!(window_lengths(&windows), [23 * 3600, 24 * 3600]);
+    }
+
+    #[test]
+    fn splits_days_across_fall_back() {
+        // 2021-11-07T00:00:00-04:00 to 2021-11-08T00:00:00-05:00
+        let windows =
+            day_windows("1636257600.000000", Some("1636347600.000000"), New_York).unwrap();
+
+        assert_eq!(
+            windows,
+            [(
+                "1636257600.000000".to_owned(),
+                "1636347600.000000".to_owned()
+            )]
+        );
+        assert_eq!(window_lengths(&windows), [25 * 3600]);
+    }
+
+    #[test]
+    fn starts_days_that_skip_midnight_an_hour_later() {
+        // São Paulo sprang forward at midnight on 2018-11-04, so that day started at 01:00
+        let windows = day_windows(
+            "1541300000.000000",
+            Some("1541383200.000000"),
+            chrono_tz::America::Sao_Paulo,
+        )
+        .unwrap();
+
+        assert_eq!(windows[0].0, "1541300399.999999");
+        assert_eq!(windows.len(), 2);
+    }
+
+    #[test]
+    fn bare_from_date_is_local_midnight() {
+        // 2021-11-07T04:00:00Z, still EDT at midnight
+        assert_eq!(
+            to_slack_timestamp("2021-11-07", New_York).unwrap(),
+            "1636257600.000000"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use super::{dates, Configuration, Slack};
+use chrono::SecondsFormat;
+use chrono_tz::Tz;
+
+/// Annotations added to each message before it's written
+#[derive(Debug, Default)]
+pub struct Enricher {
+    add_permalinks: bool,
+    workspace_url: Option<String>,
+    add_epoch_ms: bool,
+    add_iso_ts: bool,
+    timezone: Tz,
+    resolve_names: bool,
+}
+
+impl Enricher {
+    /// Add the configured annotations to a message
+    pub fn apply(&self, slack: &Slack, message: &mut serde_json::Value) -> anyhow::Result<()> {
+        let ts = match message.get("ts").and_then(serde_json::Value::as_str) {
+            Some(ts) => ts.to_owned(),
+            None => return Ok(()),
+        };
+
+        let fields = match message.as_object_mut() {
+            Some(fields) => fields,
+            None => return Ok(()),
+        };
+
+        if self.add_permalinks {
+            fields.insert("permalink".into(), slack.permalink(&ts)?.into());
+        }
+
+        // build the same link that chat.getPermalink would return, without asking for it
+        if let Some(workspace_url) = &self.workspace_url {
+            let thread_ts = fields.get("thread_ts").and_then(serde_json::Value::as_str);
+
+            if let Some(permalink) = local_permalink(workspace_url, slack.channel(), &ts, thread_ts)
+            {
+                fields.insert("permalink".into(), permalink.into());
+            }
+        }
+
+        // users missing from the listing (e.g. deleted since) are left without a name
+        if self.resolve_names {
+            let name = fields
+                .get("user")
+                .and_then(serde_json::Value::as_str)
+                .and_then(|user| slack.user_name(user));
+
+            if let Some(name) = name {
+                fields.insert("_slump_user_name".into(), name.into());
+            }
+        }
+
+        // keep the original string ts, which is more precise than either annotation
+        if let Some(datetime) = dates::from_slack_timestamp(&ts) {
+            if self.add_epoch_ms {
+                fields.insert("ts_epoch".into(), datetime.timestamp_millis().into());
+            }
+
+            if self.add_iso_ts {
+                let local = datetime.with_timezone(&self.timezone);
+                fields.insert(
+                    "ts_iso".into(),
+                    local.to_rfc3339_opts(SecondsFormat::Secs, true).into(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&Configuration> for Enricher {
+    type Error = anyhow::Error;
+
+    fn try_from(configuration: &Configuration) -> Result<Self, Self::Error> {
+        Ok(Self {
+            add_permalinks: configuration.add_permalinks,
+            workspace_url: configuration
+                .workspace_url
+                .as_deref()
+                .filter(|_| configuration.local_permalinks)
+                .map(|url| url.trim_end_matches('/').to_owned()),
+            add_epoch_ms: configuration.add_epoch_ms,
+            add_iso_ts: configuration.add_iso_ts,
+            timezone: configuration.timezone()?,
+            resolve_names: configuration.resolve_names,
+        })
+    }
+}
+
+/// Build a message's archive link from the workspace URL, e.g.
+/// `https://example.slack.com/archives/C123/p1700000001000100`
+///
+/// The `p` path segment is the `ts` without its dot. Replies in a thread also link to their
+/// thread, as Slack's own permalinks do. A `ts` that isn't in Slack's `seconds.microseconds`
+/// format has no link.
+fn local_permalink(
+    workspace_url: &str,
+    channel: &str,
+    ts: &str,
+    thread_ts: Option<&str>,
+) -> Option<String> {
+    let (seconds, micros) = ts.split_once('.')?;
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+
+    if !is_digits(seconds) || micros.len() != 6 || !is_digits(micros) {
+        return None;
+    }
+
+    let mut permalink = format!(
+        "{}/archives/{}/p{}{}",
+        workspace_url, channel, seconds, micros
+    );
+
+    if let Some(thread_ts) = thread_ts.filter(|thread_ts| *thread_ts != ts) {
+        permalink.push_str(&format!("?thread_ts={}&cid={}", thread_ts, channel));
+    }
+
+    Some(permalink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static WORKSPACE_URL: &str = "https://example.slack.com";
+
+    #[test]
+    fn links_to_messages_in_the_archive() {
+        assert_eq!(
+            local_permalink(WORKSPACE_URL, "C123", "1700000001.000100", None).as_deref(),
+            Some("https://example.slack.com/archives/C123/p1700000001000100")
+        );
+    }
+
+    #[test]
+    fn links_thread_parents_without_their_thread() {
+        assert_eq!(
+            local_permalink(
+                WORKSPACE_URL,
+                "C123",
+                "1700000001.000100",
+                Some("1700000001.000100")
+            )
+            .as_deref(),
+            Some("https://example.slack.com/archives/C123/p1700000001000100")
+        );
+    }
+
+    #[test]
+    fn links_replies_to_their_thread() {
+        assert_eq!(
+            local_permalink(
+                WORKSPACE_URL,
+                "C123",
+                "1700000002.000200",
+                Some("1700000001.000100")
+            )
+            .as_deref(),
+            Some("https://example.slack.com/archives/C123/p1700000002000200?thread_ts=1700000001.000100&cid=C123")
+        );
+    }
+
+    #[test]
+    fn leaves_malformed_timestamps_without_a_link() {
+        for ts in [
+            "1700000001",
+            "1700000001.0001",
+            "1700000001.0001000",
+            ".000100",
+            "17000x0001.000100",
+            "1700000001.00010a",
+        ] {
+            assert_eq!(
+                local_permalink(WORKSPACE_URL, "C123", ts, None),
+                None,
+                "{}",
+                ts
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use reqwest::StatusCode;
+use std::{fmt, time::Duration};
+
+/// Errors from the Slack API that library callers may want to handle specifically
+///
+/// These are returned inside `anyhow::Error`, so match on them with
+/// `error.downcast_ref::<Error>()`.
+#[derive(Debug)]
+pub enum Error {
+    /// Slack responded with `ok: false` and an error code (e.g. `channel_not_found`)
+    Api {
+        /// The error code from the response
+        error: String,
+    },
+    /// Slack rejected a request because the token lacks a scope (e.g. `mpim:history` for group DMs)
+    MissingScope {
+        /// The scope the request needed, if Slack reported it
+        needed: Option<String>,
+        /// The scopes the token has, comma-separated (from the `x-oauth-scopes` header)
+        provided: Option<String>,
+        /// The scopes the method accepts, comma-separated (from the `x-accepted-oauth-scopes`
+        /// header)
+        accepted: Option<String>,
+    },
+    /// Slack rejected the token itself (`token_revoked`, `account_inactive` or `no_permission`),
+    /// which retrying won't fix
+    Auth {
+        /// The error code from the response
+        error: String,
+    },
+    /// Slack responded with a server error (5xx), e.g. during an incident
+    Server {
+        /// The HTTP status of the response
+        status: StatusCode,
+    },
+    /// Slack rejected a request for exceeding its rate limit
+    RateLimited {
+        /// How long Slack asked to wait before retrying (from the `Retry-After` header)
+        retry_after: Option<Duration>,
+    },
+}
+
+impl Error {
+    /// A short, stable code for the error, using Slack's own error code where there is one
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Api { error } | Self::Auth { error } => error,
+            Self::MissingScope { .. } => "missing_scope",
+            Self::Server { .. } => "server_error",
+            Self::RateLimited { .. } => "ratelimited",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Api { error } => {
+                write!(
+                    formatter,
+                    "Error fetching data from the Slack API: {}",
+                    error
+                )
+            }
+            Self::MissingScope {
+                needed,
+                provided,
+                accepted,
+            } => {
+                match needed {
+                    Some(needed) => write!(
+                        formatter,
+                        "The Slack API token is missing the {} scope: add it to the app and reinstall it",
+                        needed
+                    )?,
+                    None => write!(formatter, "The Slack API token is missing a required scope")?,
+                }
+
+                // spell out both sides, since the fix is adding one of the accepted scopes
+                let scopes: Vec<_> = [
+                    accepted.as_ref().map(|accepted| format!("accepted: {}", accepted)),
+                    provided.as_ref().map(|provided| format!("token has: {}", provided)),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+
+                if scopes.is_empty() {
+                    return Ok(());
+                }
+
+                write!(formatter, " (scopes {})", scopes.join("; "))
+            }
+            Self::Auth { error } if error == "token_revoked" => write!(
+                formatter,
+                "The Slack API token has been revoked: generate a new one"
+            ),
+            Self::Auth { error } if error == "account_inactive" => write!(
+                formatter,
+                "The Slack API token belongs to a deactivated user or workspace: use a token for an active one"
+            ),
+            Self::Auth { error } if error == "no_permission" => write!(
+                formatter,
+                "The Slack API token isn't permitted to make this request: check the app's access with a workspace admin"
+            ),
+            Self::Auth { error } => {
+                write!(formatter, "The Slack API rejected the token: {}", error)
+            }
+            Self::Server { status } => write!(formatter, "Slack API responded with {}", status),
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                formatter,
+                "Rate limited by the Slack API (retry after {}s)",
+                retry_after.as_secs()
+            ),
+            Self::RateLimited { retry_after: None } => {
+                write!(formatter, "Rate limited by the Slack API")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,310 @@
+use super::{query::Query, Configuration};
+
+/// Predicates deciding which messages are included in an export
+#[derive(Debug, Default)]
+pub struct Filter {
+    only_humans: bool,
+    only_bots: bool,
+    only_edited: bool,
+    only_with_files: bool,
+    exclude_thread_replies: bool,
+    min_text_length: Option<usize>,
+    only_metadata_type: Option<String>,
+    query: Option<Query>,
+    sample_every: Option<usize>,
+    matched: usize,
+    too_short: u64,
+    without_files: u64,
+}
+
+impl Filter {
+    /// Check whether a message should be included in the export
+    pub fn matches(&mut self, message: &serde_json::Value) -> bool {
+        if self.only_humans && is_bot(message) {
+            return false;
+        }
+
+        if self.only_bots && !is_bot(message) {
+            return false;
+        }
+
+        if self.only_edited && message.get("edited").is_none() {
+            return false;
+        }
+
+        if self.only_with_files && !has_files(message) {
+            self.without_files += 1;
+            return false;
+        }
+
+        if self.exclude_thread_replies && is_thread_reply(message) {
+            return false;
+        }
+
+        if let Some(min_text_length) = self.min_text_length {
+            if is_too_short(message, min_text_length) {
+                self.too_short += 1;
+                return false;
+            }
+        }
+
+        if let Some(event_type) = &self.only_metadata_type {
+            if metadata_event_type(message) != Some(event_type) {
+                return false;
+            }
+        }
+
+        if let Some(query) = &self.query {
+            if !query.matches(message) {
+                return false;
+            }
+        }
+
+        // sample from the messages that pass every other predicate
+        let index = self.matched;
+        self.matched += 1;
+
+        match self.sample_every {
+            Some(sample_every) => index.is_multiple_of(sample_every),
+            None => true,
+        }
+    }
+
+    /// Count the messages left out for having too little text
+    pub fn too_short(&self) -> u64 {
+        self.too_short
+    }
+
+    /// Count the messages left out for not sharing any files
+    pub fn without_files(&self) -> u64 {
+        self.without_files
+    }
+}
+
+impl TryFrom<&Configuration> for Filter {
+    type Error = anyhow::Error;
+
+    fn try_from(configuration: &Configuration) -> Result<Self, Self::Error> {
+        let query = configuration
+            .jq_filter
+            .as_deref()
+            .map(Query::compile)
+            .transpose()?;
+
+        Ok(Self {
+            only_humans: configuration.only_humans,
+            only_bots: configuration.only_bots,
+            only_edited: configuration.only_edited,
+            only_with_files: configuration.only_with_files,
+            exclude_thread_replies: configuration.exclude_thread_replies,
+            min_text_length: configuration.min_text_length,
+            only_metadata_type: configuration.only_metadata_type.clone(),
+            sample_every: configuration.sample_every,
+            query,
+            matched: 0,
+            too_short: 0,
+            without_files: 0,
+        })
+    }
+}
+
+/// Check whether a message is a reply in a thread rather than the thread's parent
+///
+/// The history only includes replies that were also sent to the channel (`thread_broadcast`).
+fn is_thread_reply(message: &serde_json::Value) -> bool {
+    let ts = message.get("ts");
+
+    message
+        .get("thread_ts")
+        .is_some_and(|thread_ts| !thread_ts.is_null() && Some(thread_ts) != ts)
+}
+
+/// Check whether a message's trimmed text is shorter than a minimum length, in characters
+///
+/// Messages sharing files or attachments have content beyond their text, so they're never too
+/// short.
+fn is_too_short(message: &serde_json::Value, min_text_length: usize) -> bool {
+    let has_content = ["files", "attachments"].into_iter().any(|key| {
+        message
+            .get(key)
+            .and_then(serde_json::Value::as_array)
+            .is_some_and(|items| !items.is_empty())
+    });
+
+    let length = message
+        .get("text")
+        .and_then(serde_json::Value::as_str)
+        .map(|text| text.trim().chars().count())
+        .unwrap_or_default();
+
+    length < min_text_length && !has_content
+}
+
+/// Check whether a message shares at least one file
+fn has_files(message: &serde_json::Value) -> bool {
+    message
+        .get("files")
+        .and_then(serde_json::Value::as_array)
+        .is_some_and(|files| !files.is_empty())
+}
+
+/// Look up the `event_type` of the metadata an app attached to a message, if it has any
+fn metadata_event_type(message: &serde_json::Value) -> Option<&str> {
+    message
+        .get("metadata")
+        .and_then(|metadata| metadata.get("event_type"))
+        .and_then(serde_json::Value::as_str)
+}
+
+/// Check whether a message was posted by a bot or integration
+fn is_bot(message: &serde_json::Value) -> bool {
+    message
+        .get("bot_id")
+        .is_some_and(|bot_id| !bot_id.is_null())
+        || message.get("subtype").and_then(serde_json::Value::as_str) == Some("bot_message")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The `ts` of each message that a filter keeps, in order
+    fn kept(mut filter: Filter, messages: &[serde_json::Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .filter(|message| filter.matches(message))
+            .map(|message| message["ts"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_only_humans_or_only_bots() {
+        let messages = [
+            json!({ "ts": "1", "user": "U1" }),
+            json!({ "ts": "2", "bot_id": "B1" }),
+            json!({ "ts": "3", "subtype": "bot_message" }),
+            json!({ "ts": "4", "user": "U1", "bot_id": null }),
+        ];
+
+        let only_humans = Filter {
+            only_humans: true,
+            ..Filter::default()
+        };
+        let only_bots = Filter {
+            only_bots: true,
+            ..Filter::default()
+        };
+
+        assert_eq!(kept(only_humans, &messages), ["1", "4"]);
+        assert_eq!(kept(only_bots, &messages), ["2", "3"]);
+    }
+
+    #[test]
+    fn keeps_only_edited_messages() {
+        let messages = [
+            json!({ "ts": "1", "edited": { "user": "U1", "ts": "2" } }),
+            json!({ "ts": "2" }),
+        ];
+        let filter = Filter {
+            only_edited: true,
+            ..Filter::default()
+        };
+
+        assert_eq!(kept(filter, &messages), ["1"]);
+    }
+
+    #[test]
+    fn keeps_only_messages_with_files_counting_the_rest() {
+        let messages = [
+            json!({ "ts": "1", "files": [{ "id": "F1" }] }),
+            json!({ "ts": "2", "files": [] }),
+            json!({ "ts": "3" }),
+        ];
+        let mut filter = Filter {
+            only_with_files: true,
+            ..Filter::default()
+        };
+
+        let kept: Vec<_> = messages
+            .iter()
+            .map(|message| filter.matches(message))
+            .collect();
+
+        assert_eq!(kept, [true, false, false]);
+        assert_eq!(filter.without_files(), 2);
+    }
+
+    #[test]
+    fn excludes_thread_replies_but_not_parents() {
+        let messages = [
+            json!({ "ts": "1", "thread_ts": "1" }),
+            json!({ "ts": "2", "thread_ts": "1", "subtype": "thread_broadcast" }),
+            json!({ "ts": "3", "thread_ts": null }),
+            json!({ "ts": "4" }),
+        ];
+        let filter = Filter {
+            exclude_thread_replies: true,
+            ..Filter::default()
+        };
+
+        assert_eq!(kept(filter, &messages), ["1", "3", "4"]);
+    }
+
+    #[test]
+    fn leaves_out_messages_with_too_little_text() {
+        let messages = [
+            json!({ "ts": "1", "text": "  ok  " }),
+            json!({ "ts": "2", "text": "okay" }),
+            json!({ "ts": "3", "text": "" , "files": [{ "id": "F1" }] }),
+            json!({ "ts": "4", "text": "héé" }),
+            json!({ "ts": "5" }),
+        ];
+        let mut filter = Filter {
+            min_text_length: Some(3),
+            ..Filter::default()
+        };
+
+        let kept: Vec<_> = messages
+            .iter()
+            .map(|message| filter.matches(message))
+            .collect();
+
+        // lengths are counted in characters of the trimmed text
+        assert_eq!(kept, [false, true, true, true, false]);
+        assert_eq!(filter.too_short(), 2);
+    }
+
+    #[test]
+    fn keeps_only_messages_with_the_metadata_type() {
+        let messages = [
+            json!({ "ts": "1", "metadata": { "event_type": "deploy", "event_payload": {} } }),
+            json!({ "ts": "2", "metadata": { "event_type": "incident" } }),
+            json!({ "ts": "3" }),
+        ];
+        let filter = Filter {
+            only_metadata_type: Some("deploy".into()),
+            ..Filter::default()
+        };
+
+        assert_eq!(kept(filter, &messages), ["1"]);
+    }
+
+    #[test]
+    fn samples_from_the_messages_that_pass_every_other_predicate() {
+        let messages: Vec<_> = (1..=7)
+            .map(|ts| match ts % 3 {
+                0 => json!({ "ts": ts.to_string(), "bot_id": "B1" }),
+                _ => json!({ "ts": ts.to_string(), "user": "U1" }),
+            })
+            .collect();
+        let filter = Filter {
+            only_humans: true,
+            sample_every: Some(2),
+            ..Filter::default()
+        };
+
+        // the humans are 1, 2, 4, 5 and 7, of which every second one is kept
+        assert_eq!(kept(filter, &messages), ["1", "4", "7"]);
+    }
+}
@@ -1,42 +1,1865 @@
-use fallible_iterator::FallibleIterator;
-use serde::Deserialize;
-use slack::Slack;
-use std::io::{stdout, BufWriter, Write};
+use chrono::{SecondsFormat, Utc};
+use chrono_tz::Tz;
+use dialoguer::Select;
+use slump::{
+    flatten_blocks, newest_ts_in, validate_export, write_manifest, write_schema, Anonymizer,
+    ArrayWriter, Checkpoint, ChronologicalSorter, Collapser, Compressed, Compression,
+    Configuration, DelimitedWriter, Enricher, EnvelopeWriter, FanOut, Filter, ManifestEntry,
+    Messages, OnExisting, OutputFormat, Progress, ReactionWriter, Seed, Sink, Slack, Stats,
+    ThreadSplitter, UserSplitter, MANIFEST_FILE_NAME,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{stderr, stdin, stdout, BufWriter, ErrorKind, IsTerminal, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing_subscriber::EnvFilter;
 
-mod slack;
+mod self_test;
 
-/// Configurable values from the environment
-#[derive(Deserialize)]
-struct Configuration {
-    api_token: String,
-    channel: String,
-}
+/// Seconds to wait between polls for new messages in follow mode unless configured otherwise
+static DEFAULT_POLL_INTERVAL_SECS: u64 = 30;
+
+/// Key of the messages array in the envelope written with the channel's metadata
+static DEFAULT_ENVELOPE_KEY: &str = "messages";
+
+/// Version of the header line written with `NDJSON_HEADER`, bumped whenever its fields change
+static NDJSON_SCHEMA_VERSION: u64 = 1;
+
+/// Line endings of NDJSON output, with `CRLF` for consumers expecting Windows line endings
+static LF: &[u8] = b"\n";
+static CRLF: &[u8] = b"\r\n";
+
+/// Delimiter of `OUTPUT_FORMAT=nul` records, which never appears in compact JSON
+static NUL: &[u8] = b"\0";
+
+/// Messages (about one page) that the fetcher can get ahead of the writer when pipelining
+static PIPELINE_CAPACITY: usize = 1000;
+
+/// Messages held in memory while sorting chronologically unless configured otherwise
+static DEFAULT_SPILL_THRESHOLD: usize = 100_000;
 
-/// Stream the entire conversation history to stdout
+/// Exit code when the export stops early at `DEADLINE_SECS`, matching `timeout(1)`
+static DEADLINE_EXIT_CODE: i32 = 124;
+
+/// Capacity of the output buffer unless configured otherwise (matching the standard library)
+static DEFAULT_BUFFER_BYTES: usize = 8 * 1024;
+
+/// Stream the entire conversation history to stdout (or a file)
 fn main() -> anyhow::Result<()> {
+    // log to stderr (keeping stdout clean for the export), showing warnings unless configured
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    // check that the binary works against a mock Slack API, without needing any configuration
+    if std::env::args().nth(1).as_deref() == Some("self-test") {
+        return self_test::run();
+    }
+
+    // check that Slack can be reached at all, e.g. as a startup probe
+    if std::env::args().nth(1).as_deref() == Some("ping") {
+        return ping();
+    }
+
     // generate the configuration
-    let configuration: Configuration = envy::from_env()?;
+    let configuration = Configuration::from_env()?;
+    let json_errors = configuration.json_errors;
+
+    match run(configuration) {
+        // report the failure as a single line for log aggregation, instead of anyhow's chain
+        Err(error) if json_errors => {
+            eprintln!("{}", error_json(&error));
+            std::process::exit(exit_code(&error).unwrap_or(1));
+        }
+        // a failing PIPE_TO command fails the export with its own exit code
+        Err(error) => match exit_code(&error) {
+            Some(code) => {
+                eprintln!("Error: {:?}", error);
+                std::process::exit(code);
+            }
+            None => Err(error),
+        },
+        result => result,
+    }
+}
+
+/// Print the round trip time to the Slack API, failing if it can't be reached
+///
+/// Pinging needs neither credentials nor a channel, so the configuration is only read for its HTTP
+/// settings, falling back to the defaults when it's incomplete (e.g. without `API_TOKEN`).
+fn ping() -> anyhow::Result<()> {
+    let configuration = Configuration::from_env().unwrap_or_default();
+    let client = Slack::default_client(&configuration)?;
+
+    // listing conversations is the one mode that doesn't need a channel
+    let configuration = Configuration {
+        channel: String::new(),
+        list_channels: true,
+        ..configuration
+    };
+    let elapsed = Slack::with_client(configuration, client)?.ping()?;
+
+    println!("Slack API reachable in {}ms", elapsed.as_millis());
+
+    Ok(())
+}
+
+/// Failure of the `PIPE_TO` command that the export was streamed to
+#[derive(Debug)]
+struct PipeFailed {
+    status: ExitStatus,
+}
+
+impl fmt::Display for PipeFailed {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "PIPE_TO command failed with {}", self.status)
+    }
+}
+
+impl std::error::Error for PipeFailed {}
+
+/// Exit code of the `PIPE_TO` command behind a failure, if that's what failed
+fn exit_code(error: &anyhow::Error) -> Option<i32> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<PipeFailed>())
+        .and_then(|failure| failure.status.code())
+}
+
+/// Start a command to stream the export to, writing to its stdin
+fn spawn_pipe(command: &[String]) -> anyhow::Result<Child> {
+    let (program, arguments) = command
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("PIPE_TO must include a command"))?;
+
+    Command::new(program)
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|error| anyhow::anyhow!("Error starting PIPE_TO command {}: {}", program, error))
+}
+
+/// Wait for the `PIPE_TO` command (if any) to exit once its stdin is closed, checking that it
+/// succeeded
+fn wait_for_pipe(child: Option<Child>) -> anyhow::Result<()> {
+    if let Some(mut child) = child {
+        let status = child.wait()?;
+
+        if !status.success() {
+            return Err(PipeFailed { status }.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Export every configured channel (or list the conversations that could be exported)
+fn run(mut configuration: Configuration) -> anyhow::Result<()> {
+    // let someone who doesn't know the channel's ID pick it instead
+    if configuration.interactive && configuration.channel.is_empty() && !configuration.list_channels
+    {
+        configuration.channel = pick_channel(&configuration)?;
+    }
+
+    // show what was read even if it turns out to be invalid, without any secrets
+    if configuration.print_config {
+        eprintln!("{}", serde_json::to_string_pretty(&configuration)?);
+    }
+
+    configuration.validate()?;
+    let configuration = configuration.expand_recent();
+
+    // time-box the whole run, however many channels it exports
+    let deadline = configuration
+        .deadline_secs
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    // list the conversations that could be exported instead of exporting one
+    if configuration.list_channels {
+        let slack: Slack = configuration.clone().try_into()?;
+        let mut sink =
+            ArrayWriter::new(BufWriter::new(stdout())).with_indent(configuration.indent());
+
+        for channel in slack.channels(&configuration.channel_types)? {
+            sink.write(&channel)?;
+        }
 
-    // fetch the stream of messages from Slack
-    let slack: Slack = configuration.into();
-    let mut messages = slack.messages()?.peekable();
+        return sink.finish();
+    }
+
+    let mut stats = Stats::new(configuration.timezone()?);
+    let channels = configuration.channels();
+    let mut failures = 0;
+    let mut reached_deadline = false;
+
+    // share a single connection pool and the methods' rate limits between every channel
+    let client = Slack::default_client(&configuration)?;
+    let rate_limits = Arc::new(Slack::default_rate_limits());
+
+    // count the pages of every channel on the same progress line
+    let progress = configuration
+        .progress
+        .then(|| Arc::new(Mutex::new(Progress::new(configuration.expected_messages))));
+
+    // names of the workspace's users, listed with the first channel that needs them
+    let mut user_names = None;
+
+    // guard against templates that would write several channels to the same file
+    let mut output_paths = HashSet::new();
+
+    // describe each file of a multi-file export once they've all been written
+    let manifest_directory = configuration
+        .output_dir
+        .clone()
+        .or_else(|| configuration.output_template.as_ref().map(|_| ".".into()));
+    let mut manifest = Vec::new();
+
+    // read the previous export to merge with before its file could be overwritten
+    let seed = configuration
+        .merge_with
+        .as_deref()
+        .map(Seed::read)
+        .transpose()?;
+
+    // continue from the newest message of a previous export, if there is one
+    let since = configuration
+        .since_file
+        .as_deref()
+        .map(newest_ts_in)
+        .transpose()?
+        .flatten();
+
+    // pick up where an interrupted export left off, unless starting over
+    let checkpoint = configuration
+        .checkpoint_file()
+        .map(|path| configuration.checkpoint_policy.load(path))
+        .transpose()?
+        .flatten();
+
+    // reactions from every channel go to the same side file
+    let mut reactions = configuration
+        .reactions_output
+        .as_deref()
+        .filter(|_| !configuration.dry_run)
+        .map(ReactionWriter::create)
+        .transpose()?;
+
+    // every channel's messages have the same shape
+    if let Some(path) = configuration
+        .emit_schema
+        .as_deref()
+        .filter(|_| !configuration.dry_run)
+    {
+        write_schema(path, &configuration)?;
+    }
+
+    for channel in &channels {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            tracing::warn!(
+                "Deadline reached, skipping channel {} and any after it",
+                channel
+            );
+            stats.record_deadline();
+            reached_deadline = true;
+            break;
+        }
+
+        let configuration = configuration.for_channel(channel);
+        stats.start_channel(channel);
+
+        let slack = Slack::with_client(configuration.clone(), client.clone()).map(|slack| {
+            let slack = slack.with_rate_limits(Arc::clone(&rate_limits));
+
+            // only fetch the messages sent since the previous export
+            let newest_ts = seed
+                .as_ref()
+                .and_then(Seed::newest_ts)
+                .into_iter()
+                .chain(since.as_deref());
+
+            let slack = newest_ts.fold(slack, Slack::with_oldest);
+
+            let slack = match &checkpoint {
+                Some(checkpoint) => slack.with_checkpoint(checkpoint.clone()),
+                None => slack,
+            };
+
+            let slack = progress
+                .clone()
+                .into_iter()
+                .fold(slack, Slack::with_progress);
+
+            deadline.into_iter().fold(slack, Slack::with_deadline)
+        });
+
+        // list the workspace's users once, with the first channel, however many are exported
+        let slack = slack.and_then(|slack| {
+            if !configuration.resolve_names {
+                return Ok(slack);
+            }
+
+            let names = match &user_names {
+                Some(names) => Arc::clone(names),
+                None => Arc::clone(user_names.insert(Arc::new(slack.user_names()?))),
+            };
+
+            Ok(slack.with_user_names(names))
+        });
+
+        let result = match slack {
+            Ok(slack) => {
+                let result = output_path(&configuration, &slack)
+                    .and_then(|output_path| match output_path {
+                        Some(path) if !output_paths.insert(path.clone()) => Err(anyhow::anyhow!(
+                            "Output path {} is used by more than one channel",
+                            path.display()
+                        )),
+                        output_path => Ok(output_path),
+                    })
+                    .and_then(|output_path| {
+                        export_with_restart(
+                            &configuration,
+                            &slack,
+                            output_path,
+                            manifest_directory.as_deref(),
+                            seed.as_ref(),
+                            reactions.as_mut(),
+                            &mut stats,
+                        )
+                    });
+                stats.record_requests(&slack);
 
-    // set up exclusive access to stdout
-    let stdout = stdout();
-    let mut out = BufWriter::new(stdout.lock());
+                if slack.reached_deadline() {
+                    tracing::warn!("Deadline reached, the export of {} is partial", channel);
+                    stats.record_deadline();
+                    reached_deadline = true;
+                }
 
-    // generate a single array of messages
-    out.write_all(b"[")?;
+                result
+            }
+            Err(error) => Err(error),
+        };
 
-    while let Some(message) = messages.next()? {
-        serde_json::to_writer(out.by_ref(), &message)?;
+        stats.finish_channel(result.as_ref().err());
 
-        if messages.peek().transpose().is_some() {
-            out.write_all(b",")?;
+        // keep going with the remaining channels in best-effort mode, failing at the end instead
+        match result {
+            Err(error) if configuration.best_effort => {
+                tracing::error!("Skipping channel {} after an error: {}", channel, error);
+                failures += 1;
+            }
+            result => manifest.extend(result?),
         }
     }
 
-    out.write_all(b"]")?;
+    if configuration.dry_run {
+        return Ok(());
+    }
+
+    if let Some(reactions) = &mut reactions {
+        reactions.finish()?;
+    }
+
+    // summarize the export once every message has been written
+    stats.complete();
+
+    if let Some(stats_file) = &configuration.stats_file {
+        stats.write_to(stats_file)?;
+    }
+
+    if let Some(metrics_file) = &configuration.metrics_file {
+        stats.write_metrics_to(metrics_file)?;
+    }
+
+    if let Some(directory) = &manifest_directory {
+        fs::create_dir_all(directory)?;
+        write_manifest(&directory.join(MANIFEST_FILE_NAME), &manifest)?;
+    }
+
+    if failures > 0 {
+        anyhow::bail!(
+            "{} of {} channels failed to export",
+            failures,
+            channels.len()
+        );
+    }
+
+    // a partial export is still valid, but shouldn't look like a complete one to a scheduler
+    if reached_deadline {
+        std::process::exit(DEADLINE_EXIT_CODE);
+    }
 
     Ok(())
 }
+
+/// Export a channel, restarting its export once after a failure with `RESTART_ON_FAILURE`
+///
+/// The restart walks the channel from the same starting point as the failed attempt and replaces
+/// its partial output, so the output never holds a message twice. Failures of the token itself
+/// (including missing scopes) would only fail again, so they're never restarted.
+fn export_with_restart(
+    configuration: &Configuration,
+    slack: &Slack,
+    output_path: Option<PathBuf>,
+    manifest_directory: Option<&Path>,
+    seed: Option<&Seed>,
+    mut reactions: Option<&mut ReactionWriter>,
+    stats: &mut Stats,
+) -> anyhow::Result<Option<ManifestEntry>> {
+    if !configuration.restart_on_failure {
+        return export(
+            configuration,
+            slack,
+            output_path,
+            manifest_directory,
+            seed,
+            reactions,
+            stats,
+        );
+    }
+
+    // the failed attempt's messages are replaced, so they shouldn't be counted either
+    let snapshot = stats.clone();
+
+    let exported = export(
+        configuration,
+        slack,
+        output_path.clone(),
+        manifest_directory,
+        seed,
+        reactions.as_deref_mut(),
+        stats,
+    );
+
+    let error = match exported {
+        Err(error) if !is_token_error(&error) => error,
+        exported => return exported,
+    };
+
+    tracing::warn!(
+        "Export of {} failed, restarting it once from the start: {:#}",
+        slack.channel(),
+        error
+    );
+    *stats = snapshot;
+
+    // the failed attempt already created the output files
+    let configuration = Configuration {
+        on_existing: OnExisting::Overwrite,
+        ..configuration.clone()
+    };
+
+    export(
+        &configuration,
+        slack,
+        output_path,
+        manifest_directory,
+        seed,
+        reactions,
+        stats,
+    )
+    .map_err(|error| error.context("Export failed again after restarting it"))
+}
+
+/// Check whether a failure came from the token itself, rather than from the export
+fn is_token_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<slump::Error>(),
+        Some(slump::Error::Auth { .. } | slump::Error::MissingScope { .. })
+    )
+}
+
+/// Describe a failure as a JSON object, with the Slack error code when there is one
+fn error_json(error: &anyhow::Error) -> serde_json::Value {
+    let code = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<slump::Error>())
+        .map(slump::Error::code);
+
+    serde_json::json!({
+        "error": format!("{:#}", error),
+        "code": code,
+    })
+}
+
+/// Ask for the channel to export, choosing from the conversations visible to the token
+fn pick_channel(configuration: &Configuration) -> anyhow::Result<String> {
+    if !stdin().is_terminal() || !stderr().is_terminal() {
+        anyhow::bail!("INTERACTIVE can only pick a channel in a terminal, so set CHANNEL instead");
+    }
+
+    let slack: Slack = configuration.clone().try_into()?;
+    let channels = slack.channels(&configuration.channel_types)?;
+
+    let ids: Vec<_> = channels
+        .iter()
+        .filter_map(|channel| channel.get("id").and_then(serde_json::Value::as_str))
+        .collect();
+
+    if ids.is_empty() {
+        anyhow::bail!("No conversations are visible to the token");
+    }
+
+    let labels: Vec<_> = channels
+        .iter()
+        .zip(&ids)
+        .map(
+            |(channel, id)| match channel.get("name").and_then(serde_json::Value::as_str) {
+                Some(name) => format!("#{} ({})", name, id),
+                None => id.to_string(),
+            },
+        )
+        .collect();
+
+    let selection = Select::new()
+        .with_prompt("Channel to export")
+        .items(&labels)
+        .default(0)
+        .interact_opt()?;
+
+    match selection {
+        Some(index) => Ok(ids[index].into()),
+        None => anyhow::bail!("No channel was picked"),
+    }
+}
+
+/// Export a single channel to its configured destination, describing the file written for a
+/// multi-file export's manifest
+fn export(
+    configuration: &Configuration,
+    slack: &Slack,
+    output_path: Option<PathBuf>,
+    manifest_directory: Option<&Path>,
+    seed: Option<&Seed>,
+    reactions: Option<&mut ReactionWriter>,
+    stats: &mut Stats,
+) -> anyhow::Result<Option<ManifestEntry>> {
+    let flush_each_message = configuration.flush_each_message;
+    let poll_interval = Duration::from_secs(
+        configuration
+            .poll_interval_secs
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+    );
+    let buffer_bytes = configuration.buffer_bytes.unwrap_or(DEFAULT_BUFFER_BYTES);
+    let mut pipeline = Pipeline {
+        flatten_blocks: configuration.flatten_blocks,
+        filter: Filter::try_from(configuration)?,
+        enricher: Enricher::try_from(configuration)?,
+        collapser: Collapser::from(configuration),
+        anonymizer: Anonymizer::from(configuration),
+        exclude_fields: configuration.exclude_fields.clone(),
+    };
+
+    // describe the export without fetching any messages
+    if configuration.dry_run {
+        let plan = Plan::from_configuration(configuration, output_path.as_deref(), poll_interval);
+
+        plan.print(slack)?;
+
+        return Ok(None);
+    }
+
+    // a file that can't be continued would have to be started over by every resumed export
+    let can_continue = can_continue(configuration)?;
+
+    if configuration.checkpoint_file().is_some() && output_path.is_some() && !can_continue {
+        anyhow::bail!(
+            "CHECKPOINT_FILE can only resume an export into an uncompressed array, NDJSON or NUL file, without INCLUDE_CHANNEL_INFO, SPLIT_BY_USER, SPLIT_BY_THREAD or EXTRA_OUTPUTS"
+        );
+    }
+
+    // a resumed export continues its output from the checkpoint's page boundary
+    let continued_len = slack
+        .checkpoint()
+        .zip(output_path.as_deref())
+        .map(|(checkpoint, path)| continued_len(checkpoint, path))
+        .transpose()?;
+
+    // give a brand-new channel the chance to receive its first message
+    if let Some(timeout) = configuration.wait_for_messages_secs {
+        wait_for_messages(slack, Duration::from_secs(timeout), poll_interval)?;
+    }
+
+    // list files by their path relative to the manifest
+    let manifest_entry = manifest_directory
+        .zip(output_path.as_deref())
+        .map(|(directory, path)| {
+            let file = path.strip_prefix(directory).unwrap_or(path);
+
+            ManifestEntry::new(file, slack.channel())
+        });
+    let mut records = Records {
+        channel: slack.channel(),
+        manifest_entry,
+        reactions,
+        continuable_output: output_path.as_deref().filter(|_| can_continue),
+    };
+
+    // write to the configured file, or to stdout (unlocked, since the Parquet writer may move
+    // between threads)
+    let mut is_appending = false;
+    let mut child = None;
+    let mut replacement = None;
+    let destination: Compressed = match &output_path {
+        Some(path) => {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(parent)?;
+            }
+
+            is_appending = configuration.on_existing == OnExisting::Append
+                && fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0);
+
+            // write a RECENT tail file beside the old one, replacing it only once it's complete
+            let file = match (configuration.recent, continued_len) {
+                (Some(_), _) => {
+                    let directory = path
+                        .parent()
+                        .filter(|parent| !parent.as_os_str().is_empty())
+                        .unwrap_or(Path::new("."));
+
+                    // create it like any other output file, rather than readable only by its owner
+                    let (file, temp_path) = tempfile::Builder::new()
+                        .prefix(".slump-recent-")
+                        .make_in(directory, |path| File::create_new(path))?
+                        .into_parts();
+
+                    // keep the permissions of the file being replaced
+                    if let Ok(metadata) = fs::metadata(path) {
+                        fs::set_permissions(&temp_path, metadata.permissions())?;
+                    }
+
+                    replacement = Some(temp_path);
+                    file
+                }
+                // drop anything written after the page boundary, like the closing bracket
+                (None, Some(len)) => {
+                    let mut file = OpenOptions::new().write(true).open(path)?;
+                    file.set_len(len)?;
+                    file.seek(SeekFrom::End(0))?;
+                    file
+                }
+                (None, None) => configuration.on_existing.open(path)?,
+            };
+
+            configuration
+                .compression
+                .wrap(file, configuration.compression_level)?
+        }
+        // stream to another program instead of stdout, closing its stdin once done
+        None => match &configuration.pipe_to {
+            Some(command) => {
+                let stdin = child
+                    .insert(spawn_pipe(command)?)
+                    .stdin
+                    .take()
+                    .ok_or_else(|| anyhow::anyhow!("PIPE_TO command has no stdin"))?;
+
+                configuration
+                    .compression
+                    .wrap(stdin, configuration.compression_level)?
+            }
+            None => configuration
+                .compression
+                .wrap(stdout(), configuration.compression_level)?,
+        },
+    };
+    let mut out = BufWriter::with_capacity(buffer_bytes, destination);
+
+    // describe the channel itself before any of its messages
+    let channel_info = if configuration.include_channel_info {
+        Some(slack.info()?)
+    } else {
+        None
+    };
+
+    // record who was in the channel at the time of the export
+    let members = if configuration.include_members {
+        Some(slack.members()?)
+    } else {
+        None
+    };
+
+    // let custom emoji in reactions and messages be resolved to their images later
+    let emoji = if configuration.include_emoji {
+        Some(slack.emoji()?)
+    } else {
+        None
+    };
+
+    // tail the channel indefinitely instead of producing a single document
+    if configuration.follow {
+        let line_ending = line_ending(configuration);
+
+        // the header describes the whole file, so it isn't repeated when appending to one
+        if configuration.ndjson_header && !is_appending {
+            write_line(&mut out, &ndjson_header(slack, members, emoji), line_ending)?;
+        }
+
+        if let Some(channel_info) = &channel_info {
+            write_line(&mut out, channel_info, line_ending)?;
+        }
+
+        let followed = follow_messages(
+            slack,
+            &mut pipeline,
+            out,
+            poll_interval,
+            flush_each_message,
+            line_ending,
+        );
+
+        // a command that exited early explains a broken pipe better than the pipe itself
+        wait_for_pipe(child)?;
+        followed?;
+
+        return Ok(None);
+    }
+
+    // generate a single array of messages, one array per thread or one file per user
+    let mut sink: Box<dyn Sink> = match configuration.output_format {
+        _ if configuration.split_by_user.is_some() => {
+            let directory = configuration
+                .split_by_user
+                .as_deref()
+                .expect("split by user");
+
+            Box::new(UserSplitter::create(directory, slack.channel())?)
+        }
+        OutputFormat::Array => match (&configuration.split_by_thread, channel_info) {
+            (Some(directory), channel_info) => {
+                let sink =
+                    ThreadSplitter::create(directory, slack.channel(), configuration.indent())?;
+
+                if let Some(channel_info) = channel_info {
+                    let file = File::create(directory.join("channel.json"))?;
+                    serde_json::to_writer(file, &channel_info)?;
+                }
+
+                Box::new(sink)
+            }
+            (None, Some(channel_info)) => {
+                let mut fields = serde_json::Map::new();
+                fields.insert("channel".into(), channel_info);
+
+                if let Some(members) = members {
+                    fields.insert("members".into(), members.into());
+                }
+
+                if let Some(emoji) = emoji {
+                    fields.insert("emoji".into(), emoji.into_iter().collect());
+                }
+
+                let messages_key = configuration
+                    .envelope_key
+                    .as_deref()
+                    .unwrap_or(DEFAULT_ENVELOPE_KEY);
+
+                Box::new(EnvelopeWriter::new(
+                    out,
+                    &fields,
+                    messages_key,
+                    configuration.indent(),
+                )?)
+            }
+            (None, None) => Box::new(
+                ArrayWriter::new(out)
+                    .with_indent(configuration.indent())
+                    .with_trailing_commas(configuration.trailing_commas)
+                    .continuing(continued_len.is_some_and(|len| len > 0)),
+            ),
+        },
+        OutputFormat::Ndjson => Box::new(DelimitedWriter::new(out, line_ending(configuration))),
+        OutputFormat::Nul => Box::new(DelimitedWriter::new(out, NUL)),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => Box::new(slump::ParquetWriter::new(out)?),
+        // the database is opened by its path, once the file has been created like any other
+        #[cfg(feature = "sqlite")]
+        OutputFormat::Sqlite => Box::new(slump::SqliteWriter::create(
+            output_path.as_deref().expect("SQLite output file"),
+        )?),
+    };
+
+    // write every message to any further outputs as well, as it arrives
+    let extra_outputs = configuration.extra_outputs()?;
+
+    if !extra_outputs.is_empty() {
+        let mut sinks = vec![sink];
+
+        for (format, path) in extra_outputs {
+            let file = configuration.on_existing.open(&path)?;
+
+            sinks.push(match format {
+                OutputFormat::Array => {
+                    let out = configuration
+                        .compression
+                        .wrap(file, configuration.compression_level)?;
+
+                    Box::new(
+                        ArrayWriter::new(BufWriter::with_capacity(buffer_bytes, out))
+                            .with_indent(configuration.indent())
+                            .with_trailing_commas(configuration.trailing_commas),
+                    )
+                }
+                OutputFormat::Ndjson => {
+                    let out = configuration
+                        .compression
+                        .wrap(file, configuration.compression_level)?;
+
+                    Box::new(DelimitedWriter::new(
+                        BufWriter::with_capacity(buffer_bytes, out),
+                        line_ending(configuration),
+                    ))
+                }
+                OutputFormat::Nul => {
+                    let out = configuration
+                        .compression
+                        .wrap(file, configuration.compression_level)?;
+
+                    Box::new(DelimitedWriter::new(
+                        BufWriter::with_capacity(buffer_bytes, out),
+                        NUL,
+                    ))
+                }
+                #[cfg(feature = "parquet")]
+                OutputFormat::Parquet => Box::new(slump::ParquetWriter::new(
+                    BufWriter::with_capacity(buffer_bytes, file),
+                )?),
+                #[cfg(feature = "sqlite")]
+                OutputFormat::Sqlite => Box::new(slump::SqliteWriter::create(&path)?),
+            });
+        }
+
+        sink = Box::new(FanOut::new(sinks));
+    }
+
+    // reorder the messages oldest first on their way to the output
+    if configuration.chronological {
+        let threshold = configuration
+            .spill_threshold
+            .unwrap_or(DEFAULT_SPILL_THRESHOLD);
+
+        let sorter = ChronologicalSorter::new(sink, threshold);
+
+        sink = match &configuration.spill_dir {
+            Some(directory) => {
+                fs::create_dir_all(directory)?;
+                Box::new(sorter.in_directory(directory))
+            }
+            None => Box::new(sorter),
+        };
+    }
+
+    // close the output even when the export fails partway, leaving a valid (if partial) document
+    let written = write_messages(
+        configuration,
+        slack,
+        &mut pipeline,
+        sink.as_mut(),
+        seed,
+        &mut records,
+        stats,
+    );
+    let finished = sink.finish();
+    stats.record_short(pipeline.filter.too_short());
+    stats.record_without_files(pipeline.filter.without_files());
+
+    // compressed output is completed as the sink finishes, and dropping its writer closes the
+    // stdin of any PIPE_TO command
+    drop(sink);
+    wait_for_pipe(child)?;
+
+    if written.is_err() && finished.is_ok() {
+        tracing::warn!("Closed the partial export after an error");
+    }
+
+    written?;
+    finished?;
+
+    // a failed export leaves the previous tail file in place, as its replacement is dropped
+    if let Some((replacement, path)) = replacement.zip(output_path.as_deref()) {
+        replacement
+            .persist(path)
+            .map_err(|error| anyhow::anyhow!("Error replacing {}: {}", path.display(), error))?;
+    }
+
+    // a finished export has nothing left to resume, unless it stopped at the deadline
+    if let Some(path) = configuration
+        .checkpoint_file()
+        .filter(|_| !slack.reached_deadline())
+    {
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                anyhow::bail!("Error removing {}: {}", path.display(), error)
+            }
+            _ => {}
+        }
+    }
+
+    // check the finished file from the start, as a downstream reader would see it
+    if configuration.validate_output {
+        if let Some(path) = &output_path {
+            validate_export(path)?;
+            tracing::info!("Validated the export in {}", path.display());
+        }
+    }
+
+    Ok(records.manifest_entry)
+}
+
+/// Check whether an export's output file could be continued from a page boundary by a resumed
+/// export, which only works when its messages are written to it as they are
+fn can_continue(configuration: &Configuration) -> anyhow::Result<bool> {
+    Ok(configuration.compression == Compression::None
+        && matches!(
+            configuration.output_format,
+            OutputFormat::Array | OutputFormat::Ndjson | OutputFormat::Nul
+        )
+        && !configuration.include_channel_info
+        && configuration.split_by_user.is_none()
+        && configuration.split_by_thread.is_none()
+        && configuration.extra_outputs()?.is_empty())
+}
+
+/// Find how much of an output file to keep when resuming its export from a checkpoint
+fn continued_len(checkpoint: &Checkpoint, path: &Path) -> anyhow::Result<u64> {
+    let len = checkpoint.output_len.ok_or_else(|| {
+        anyhow::anyhow!(
+            "The checkpoint doesn't record how much of {} was written, so it can't be continued (set CHECKPOINT_POLICY=restart to start over)",
+            path.display()
+        )
+    })?;
+
+    let written = fs::metadata(path)
+        .map_err(|error| {
+            anyhow::anyhow!(
+                "Error resuming the export into {}: {}",
+                path.display(),
+                error
+            )
+        })?
+        .len();
+
+    if written < len {
+        anyhow::bail!(
+            "{} is shorter than when the checkpoint was saved ({} of {} bytes), so it can't be continued (set CHECKPOINT_POLICY=restart to start over)",
+            path.display(),
+            written,
+            len
+        );
+    }
+
+    Ok(len)
+}
+
+/// Write every message to the sink, followed by any previous export being merged with it
+fn write_messages(
+    configuration: &Configuration,
+    slack: &Slack,
+    pipeline: &mut Pipeline,
+    sink: &mut dyn Sink,
+    seed: Option<&Seed>,
+    records: &mut Records,
+    stats: &mut Stats,
+) -> anyhow::Result<()> {
+    if configuration.pipeline {
+        // fetch on a separate thread, with a bounded queue holding back a fetcher that gets ahead
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::sync_channel(PIPELINE_CAPACITY);
+
+            scope.spawn(move || {
+                if let Err(error) = fetch_messages(configuration, slack, scope, pipeline, &sender) {
+                    let _ = sender.send(Err(error));
+                }
+            });
+
+            // stopping early drops the receiver, which stops the fetcher at its next message
+            let mut written = 0;
+
+            for message in receiver {
+                if configuration.head().is_some_and(|head| written >= head) {
+                    break;
+                }
+
+                let message = message?;
+
+                // Slack's bounds are exclusive, but guard against overlap at the boundary anyway
+                if seed.is_some_and(|seed| seed.contains(&message)) {
+                    continue;
+                }
+
+                if write_message(configuration, sink, stats, &message)? {
+                    written += 1;
+                    records.record(&message)?;
+                }
+            }
+
+            anyhow::Ok(())
+        })?;
+    } else {
+        thread::scope(|scope| {
+            let mut messages = walk(configuration, slack, scope)?;
+            let mut written = 0;
+            let mut saved_cursor = None;
+
+            // Slack returns the newest messages first, so stopping early keeps the most recent ones
+            while configuration.head().is_none_or(|head| written < head) {
+                let message = match pipeline.next(slack, &mut messages)? {
+                    Some(message) => message,
+                    None => break,
+                };
+
+                let is_seeded = seed.is_some_and(|seed| seed.contains(&message));
+
+                if !is_seeded && write_message(configuration, sink, stats, &message)? {
+                    written += 1;
+                    records.record(&message)?;
+                }
+
+                // save progress at each page boundary, once the page has reached the output
+                if let Some(path) = configuration.checkpoint_file() {
+                    let mut checkpoint = messages.checkpoint();
+
+                    if checkpoint.cursor.is_some() && checkpoint.cursor != saved_cursor {
+                        sink.flush()?;
+                        checkpoint.output_len = records
+                            .continuable_output
+                            .map(|output| fs::metadata(output).map(|metadata| metadata.len()))
+                            .transpose()?;
+                        checkpoint.write(path)?;
+                        saved_cursor = checkpoint.cursor;
+                    }
+                }
+            }
+
+            anyhow::Ok(())
+        })?;
+    }
+
+    // the previous export's messages are older, so they follow the new ones
+    for message in seed.map(Seed::messages).unwrap_or_default() {
+        if write_message(configuration, sink, stats, message)? {
+            records.record(message)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Descriptions of the messages written to a channel's export, kept alongside it
+struct Records<'a> {
+    channel: &'a str,
+    manifest_entry: Option<ManifestEntry>,
+    reactions: Option<&'a mut ReactionWriter>,
+    /// File the messages are written to, if a resumed export could continue it
+    continuable_output: Option<&'a Path>,
+}
+
+impl Records<'_> {
+    /// Describe a message that was just written to the export
+    fn record(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        if let Some(entry) = &mut self.manifest_entry {
+            entry.record(message);
+        }
+
+        if let Some(reactions) = &mut self.reactions {
+            reactions.write(self.channel, message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetch and process every message, sending them to a writer on another thread until it stops
+fn fetch_messages<'scope>(
+    configuration: &Configuration,
+    slack: &'scope Slack,
+    scope: &'scope thread::Scope<'scope, '_>,
+    pipeline: &mut Pipeline,
+    sender: &SyncSender<anyhow::Result<serde_json::Value>>,
+) -> anyhow::Result<()> {
+    let mut messages = walk(configuration, slack, scope)?;
+
+    while let Some(message) = pipeline.next(slack, &mut messages)? {
+        if sender.send(Ok(message)).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Start walking a channel's history, fetching pages ahead of time on a thread in `scope` if
+/// configured to
+fn walk<'scope>(
+    configuration: &Configuration,
+    slack: &'scope Slack,
+    scope: &'scope thread::Scope<'scope, '_>,
+) -> anyhow::Result<Messages<'scope>> {
+    if configuration.parallel_pages {
+        slack.messages_with_lookahead(scope)
+    } else {
+        slack.messages()
+    }
+}
+
+/// Write a processed message to the sink, returning whether it was written rather than skipped
+fn write_message(
+    configuration: &Configuration,
+    sink: &mut dyn Sink,
+    stats: &mut Stats,
+    message: &serde_json::Value,
+) -> anyhow::Result<bool> {
+    if configuration.skip_non_objects && !message.is_object() {
+        tracing::warn!("Skipping message that isn't a JSON object: {}", message);
+        stats.record_skipped();
+
+        return Ok(false);
+    }
+
+    // serialize into the void first so that a bad message never leaves partial output behind
+    if configuration.skip_bad_messages {
+        if let Err(error) = serde_json::to_writer(std::io::sink(), message) {
+            tracing::warn!(
+                "Skipping message {:?} that can't be serialized: {}",
+                timestamp(message),
+                error
+            );
+            stats.record_skipped();
+
+            return Ok(false);
+        }
+    }
+
+    stats.record(message);
+
+    let started = Instant::now();
+    sink.write(message)?;
+    stats.record_write(started.elapsed());
+
+    if configuration.flush_each_message {
+        sink.flush()?;
+    }
+
+    Ok(true)
+}
+
+/// Path of the file to write a channel's export to, if it isn't written to stdout
+fn output_path(configuration: &Configuration, slack: &Slack) -> anyhow::Result<Option<PathBuf>> {
+    let extension = if configuration.follow && configuration.output_format != OutputFormat::Nul {
+        "ndjson".to_owned()
+    } else {
+        format!(
+            "{}{}",
+            configuration.output_format.extension(),
+            configuration.compression.suffix()
+        )
+    };
+
+    let file_name = match (&configuration.output_template, &configuration.output_dir) {
+        (Some(template), _) => {
+            render_template(template, slack, &extension, configuration.timezone()?)?
+        }
+        (None, Some(_)) => format!("{}.{}", slack.channel(), extension),
+        (None, None) => return Ok(configuration.output_file.clone()),
+    };
+
+    match &configuration.output_dir {
+        Some(directory) => Ok(Some(directory.join(file_name))),
+        None => Ok(Some(file_name.into())),
+    }
+}
+
+/// Fill in the placeholders of an output filename template for a channel
+fn render_template(
+    template: &str,
+    slack: &Slack,
+    extension: &str,
+    timezone: Tz,
+) -> anyhow::Result<String> {
+    let date = Utc::now().with_timezone(&timezone).date_naive().to_string();
+    let mut rendered = template
+        .replace("{channel}", slack.channel())
+        .replace("{date}", &date)
+        .replace("{ext}", extension);
+
+    // only look the channel up when its name is actually needed
+    if rendered.contains("{channel_name}") {
+        let channel_info = slack.info()?;
+        let name = channel_info
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(slack.channel());
+
+        rendered = rendered.replace("{channel_name}", name);
+    }
+
+    Ok(rendered)
+}
+
+/// Summary of what an export would do, for dry runs
+struct Plan {
+    output: String,
+    include_replies: bool,
+    max_thread_requests: Option<usize>,
+    history_limit: i16,
+    replies_limit: i16,
+    add_permalinks: bool,
+    stats_file: Option<PathBuf>,
+    metrics_file: Option<PathBuf>,
+}
+
+impl Plan {
+    /// Describe the export that a configuration would run
+    fn from_configuration(
+        configuration: &Configuration,
+        output_path: Option<&Path>,
+        poll_interval: Duration,
+    ) -> Self {
+        let destination = match output_path {
+            Some(path) => format!(
+                "{} (on existing: {:?})",
+                path.display(),
+                configuration.on_existing
+            ),
+            None => "stdout".into(),
+        };
+        let output = match configuration.output_format {
+            OutputFormat::Nul if configuration.follow => format!(
+                "NUL-delimited JSON to {}, then polling for new messages every {}s",
+                destination,
+                poll_interval.as_secs()
+            ),
+            _ if configuration.follow => format!(
+                "NDJSON to {}, then polling for new messages every {}s",
+                destination,
+                poll_interval.as_secs()
+            ),
+            _ if configuration.split_by_user.is_some() => format!(
+                "one NDJSON file per user in {}",
+                configuration
+                    .split_by_user
+                    .as_deref()
+                    .unwrap_or(Path::new("."))
+                    .display()
+            ),
+            OutputFormat::Array => match &configuration.split_by_thread {
+                Some(directory) => format!("one JSON array per thread in {}", directory.display()),
+                None if configuration.include_channel_info => format!(
+                    "a JSON object with the channel and its messages to {}",
+                    destination
+                ),
+                None => format!("a JSON array to {}", destination),
+            },
+            OutputFormat::Ndjson => format!("NDJSON to {}", destination),
+            OutputFormat::Nul => format!("NUL-delimited JSON to {}", destination),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => format!("a Parquet file to {}", destination),
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => format!("an SQLite database to {}", destination),
+        };
+        let output = match configuration.compression {
+            Compression::None => output,
+            compression => format!(
+                "{}, compressed with {}",
+                output,
+                format!("{:?}", compression).to_lowercase()
+            ),
+        };
+
+        Self {
+            output,
+            include_replies: configuration.include_replies(),
+            max_thread_requests: configuration.max_thread_requests,
+            history_limit: configuration.history_limit(),
+            replies_limit: configuration.replies_limit(),
+            add_permalinks: configuration.add_permalinks,
+            stats_file: configuration.stats_file.clone(),
+            metrics_file: configuration.metrics_file.clone(),
+        }
+    }
+
+    /// Check that the channel exists, then print the plan to stderr
+    fn print(&self, slack: &Slack) -> anyhow::Result<()> {
+        let channel_info = slack.info()?;
+        let name = channel_info
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("unnamed");
+        let (oldest, latest) = slack.window();
+
+        eprintln!("Dry run: no messages will be fetched");
+        eprintln!("  channel: {} (#{})", slack.channel(), name);
+        eprintln!(
+            "  window: {} to {}",
+            oldest.unwrap_or("the start of the history"),
+            latest.unwrap_or("now")
+        );
+        eprintln!("  output: {}", self.output);
+
+        if let Some(stats_file) = &self.stats_file {
+            eprintln!("  stats: {}", stats_file.display());
+        }
+
+        if let Some(metrics_file) = &self.metrics_file {
+            eprintln!("  metrics: {}", metrics_file.display());
+        }
+
+        // the number of messages isn't known without fetching them, so estimate per message
+        eprintln!(
+            "  requests: 1 to conversations.history per {} messages",
+            self.history_limit
+        );
+
+        if self.include_replies {
+            match self.max_thread_requests {
+                Some(limit) => eprintln!(
+                    "    plus 1 to conversations.replies per {} replies in each thread (at most {} threads)",
+                    self.replies_limit, limit
+                ),
+                None => eprintln!(
+                    "    plus 1 to conversations.replies per {} replies in each thread",
+                    self.replies_limit
+                ),
+            }
+        }
+
+        if self.add_permalinks {
+            eprintln!("    plus 1 to chat.getPermalink per message (at most 100 per minute)");
+        }
+
+        Ok(())
+    }
+}
+
+/// Per-message processing shared by every kind of export
+struct Pipeline {
+    flatten_blocks: bool,
+    filter: Filter,
+    enricher: Enricher,
+    collapser: Collapser,
+    anonymizer: Anonymizer,
+    exclude_fields: Vec<String>,
+}
+
+impl Pipeline {
+    /// Filter, merge and annotate a message, returning a record only once it should be written
+    fn process(
+        &mut self,
+        slack: &Slack,
+        mut message: serde_json::Value,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        // recover the text first, so that filtering and merging see it too
+        if self.flatten_blocks {
+            flatten_blocks(&mut message);
+        }
+
+        if !self.filter.matches(&message) {
+            return Ok(None);
+        }
+
+        // annotate merged records once, based on their earliest message
+        self.collapser
+            .push(message)
+            .map(|message| self.finish_record(slack, message))
+            .transpose()
+    }
+
+    /// Pull the next record that should be written from a walk through the history
+    fn next(
+        &mut self,
+        slack: &Slack,
+        messages: &mut Messages,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        while let Some(message) = messages.try_next()? {
+            if let Some(message) = self.process(slack, message)? {
+                return Ok(Some(message));
+            }
+        }
+
+        // the last merged record is only complete once the walk has finished
+        self.collapser
+            .finish()
+            .map(|message| self.finish_record(slack, message))
+            .transpose()
+    }
+
+    /// Annotate a record that's ready to be written, then drop any excluded fields
+    fn finish_record(
+        &self,
+        slack: &Slack,
+        mut message: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.enricher.apply(slack, &mut message)?;
+        self.anonymizer.apply(&mut message);
+
+        if let Some(fields) = message.as_object_mut() {
+            for field in &self.exclude_fields {
+                fields.remove(field);
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+/// Poll an empty channel until it has a message or the timeout passes, exporting it either way
+fn wait_for_messages(
+    slack: &Slack,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+
+    while !slack.has_messages()? {
+        let elapsed = started.elapsed();
+
+        if elapsed >= timeout {
+            tracing::warn!(
+                "Channel {} still has no messages after {}s, exporting it anyway",
+                slack.channel(),
+                timeout.as_secs()
+            );
+
+            return Ok(());
+        }
+
+        tracing::info!("Waiting for the first message in {}", slack.channel());
+        thread::sleep(poll_interval.min(timeout - elapsed));
+    }
+
+    Ok(())
+}
+
+/// Stream the existing history as NDJSON, then poll for new messages until interrupted
+fn follow_messages(
+    slack: &Slack,
+    pipeline: &mut Pipeline,
+    mut out: impl Write,
+    poll_interval: Duration,
+    flush_each_message: bool,
+    line_ending: &[u8],
+) -> anyhow::Result<()> {
+    // Slack returns the newest messages first, so the first message of each walk is the latest seen
+    let mut newest = None;
+    let mut messages = slack.messages()?;
+
+    while let Some(message) = messages.try_next()? {
+        if newest.is_none() {
+            newest = timestamp(&message);
+        }
+
+        let message = match pipeline.process(slack, message)? {
+            Some(message) => message,
+            None => continue,
+        };
+
+        write_line(&mut out, &message, line_ending)?;
+
+        if flush_each_message {
+            out.flush()?;
+        }
+    }
+
+    out.flush()?;
+
+    loop {
+        thread::sleep(poll_interval);
+
+        let mut messages = match &newest {
+            Some(newest) => slack.history_since(newest)?,
+            None => slack.messages()?,
+        };
+        let mut new_messages = Vec::new();
+
+        while let Some(message) = messages.try_next()? {
+            new_messages.push(message);
+        }
+
+        if let Some(latest) = new_messages.first().and_then(timestamp) {
+            newest = Some(latest);
+        }
+
+        // emit each batch of new messages oldest-first, in the order they were sent
+        for message in new_messages.into_iter().rev() {
+            let message = match pipeline.process(slack, message)? {
+                Some(message) => message,
+                None => continue,
+            };
+
+            write_line(&mut out, &message, line_ending)?;
+
+            if flush_each_message {
+                out.flush()?;
+            }
+        }
+
+        out.flush()?;
+    }
+}
+
+/// Describe an NDJSON export in the header line that precedes its messages
+fn ndjson_header(
+    slack: &Slack,
+    members: Option<Vec<String>>,
+    emoji: Option<HashMap<String, String>>,
+) -> serde_json::Value {
+    let mut header = serde_json::json!({
+        "type": "header",
+        "schema_version": NDJSON_SCHEMA_VERSION,
+        "channel": slack.channel(),
+        "exported_at": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "generator": format!("slump {}", env!("CARGO_PKG_VERSION")),
+    });
+
+    if let Some(members) = members {
+        header["members"] = members.into();
+    }
+
+    if let Some(emoji) = emoji {
+        header["emoji"] = emoji.into_iter().collect();
+    }
+
+    header
+}
+
+/// Bytes ending each line of NDJSON output, or each record with `OUTPUT_FORMAT=nul`
+fn line_ending(configuration: &Configuration) -> &'static [u8] {
+    match configuration.output_format {
+        OutputFormat::Nul => NUL,
+        _ if configuration.crlf => CRLF,
+        _ => LF,
+    }
+}
+
+/// Write a single message as a line of NDJSON
+fn write_line(
+    out: &mut impl Write,
+    message: &serde_json::Value,
+    line_ending: &[u8],
+) -> anyhow::Result<()> {
+    serde_json::to_writer(out.by_ref(), message)?;
+    out.write_all(line_ending)?;
+
+    Ok(())
+}
+
+/// Extract the Slack timestamp of a message
+fn timestamp(message: &serde_json::Value) -> Option<String> {
+    message.get("ts")?.as_str().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+    use slump::{CheckpointPolicy, RateLimits};
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+    };
+
+    /// Serve each canned body in turn to the requests of a mock Slack API, returning its root URL
+    fn serve(bodies: Vec<serde_json::Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for (mut stream, body) in listener.incoming().flatten().zip(bodies) {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+
+                // skip the request line and headers, since there's no request body to find
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let body = body.to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        base_url
+    }
+
+    /// A page of the mock channel's history with a message for each `ts`
+    fn page(ts: &[&str], next_cursor: Option<&str>) -> serde_json::Value {
+        let messages: Vec<_> = ts
+            .iter()
+            .map(|ts| serde_json::json!({ "type": "message", "text": "hi", "ts": ts }))
+            .collect();
+
+        match next_cursor {
+            Some(next_cursor) => serde_json::json!({
+                "ok": true,
+                "messages": messages,
+                "has_more": true,
+                "response_metadata": { "next_cursor": next_cursor },
+            }),
+            None => serde_json::json!({ "ok": true, "messages": messages, "has_more": false }),
+        }
+    }
+
+    /// A client for a mock channel serving each body in turn, resuming from its checkpoint if
+    /// there is one
+    fn mock_slack(
+        configuration: &Configuration,
+        bodies: Vec<serde_json::Value>,
+    ) -> anyhow::Result<Slack> {
+        let slack = Slack::try_from(configuration.clone())?
+            .with_base_url(serve(bodies))
+            .with_rate_limits(Arc::new(RateLimits::default()));
+
+        let checkpoint = configuration
+            .checkpoint_file()
+            .map(|path| CheckpointPolicy::Resume.load(path))
+            .transpose()?
+            .flatten();
+
+        Ok(match checkpoint {
+            Some(checkpoint) => slack.with_checkpoint(checkpoint),
+            None => slack,
+        })
+    }
+
+    /// Export a channel into a file
+    fn export_into(
+        configuration: &Configuration,
+        slack: &Slack,
+        output: &Path,
+    ) -> anyhow::Result<Option<ManifestEntry>> {
+        export(
+            configuration,
+            slack,
+            Some(output.to_owned()),
+            None,
+            None,
+            None,
+            &mut Stats::new(Tz::UTC),
+        )
+    }
+
+    /// Export the mock channel into a file, resuming from its checkpoint if there is one
+    fn export_to(
+        configuration: &Configuration,
+        bodies: Vec<serde_json::Value>,
+        output: &Path,
+    ) -> anyhow::Result<Option<ManifestEntry>> {
+        export_into(configuration, &mock_slack(configuration, bodies)?, output)
+    }
+
+    /// Interrupt an export after its first page, then resume it into the same file
+    fn interrupt_and_resume(configuration: &Configuration, output: &Path) {
+        let interrupted = export_to(
+            configuration,
+            vec![
+                page(&["3.000000", "2.000000"], Some("page-2")),
+                serde_json::json!({ "ok": false, "error": "channel_not_found" }),
+            ],
+            output,
+        );
+        assert!(interrupted.is_err());
+
+        // a killed export leaves half a message behind, too
+        let mut file = OpenOptions::new().append(true).open(output).unwrap();
+        file.write_all(br#",{"ts":"1.0"#).unwrap();
+        drop(file);
+
+        export_to(configuration, vec![page(&["1.000000"], None)], output).unwrap();
+
+        assert!(!configuration.checkpoint_file().unwrap().exists());
+    }
+
+    #[test]
+    fn resumes_an_interrupted_export_into_the_same_array() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("export.json");
+        let configuration = Configuration {
+            checkpoint_file: Some(directory.path().join("checkpoint.json")),
+            pretty: true,
+            ..Configuration::new("xoxb-test", "C0123ABCD")
+        };
+
+        interrupt_and_resume(&configuration, &output);
+
+        let messages: Vec<serde_json::Value> =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        let ts: Vec<_> = messages.iter().map(|message| &message["ts"]).collect();
+        assert_eq!(ts, ["3.000000", "2.000000", "1.000000"]);
+    }
+
+    #[test]
+    fn resumes_an_interrupted_export_into_the_same_ndjson() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("export.ndjson");
+        let configuration = Configuration {
+            checkpoint_file: Some(directory.path().join("checkpoint.json")),
+            output_format: OutputFormat::Ndjson,
+            ..Configuration::new("xoxb-test", "C0123ABCD")
+        };
+
+        interrupt_and_resume(&configuration, &output);
+
+        let ts: Vec<_> = fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["ts"].clone())
+            .collect();
+        assert_eq!(ts, ["3.000000", "2.000000", "1.000000"]);
+    }
+
+    #[test]
+    fn refuses_to_resume_into_a_compressed_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let configuration = Configuration {
+            checkpoint_file: Some(directory.path().join("checkpoint.json")),
+            compression: Compression::Gzip,
+            ..Configuration::new("xoxb-test", "C0123ABCD")
+        };
+
+        let error = export_to(
+            &configuration,
+            vec![],
+            &directory.path().join("export.json.gz"),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(
+            error.starts_with("CHECKPOINT_FILE can only resume"),
+            "{}",
+            error
+        );
+    }
+
+    /// Configuration sorting the mock channel chronologically, spilling every message to its own
+    /// run in a directory of the test's
+    fn spilling(directory: &Path) -> Configuration {
+        Configuration {
+            chronological: true,
+            spill_threshold: Some(1),
+            spill_dir: Some(directory.join("spill")),
+            ..Configuration::new("xoxb-test", "C0123ABCD")
+        }
+    }
+
+    /// Files left in the directory runs are spilled to
+    fn spilled_files(directory: &Path) -> Vec<PathBuf> {
+        fs::read_dir(directory.join("spill"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn removes_spilled_runs_when_the_deadline_stops_an_export() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("export.json");
+        let configuration = spilling(directory.path());
+        let slack = mock_slack(
+            &configuration,
+            vec![page(&["3.000000", "1.000000", "2.000000"], Some("page-2"))],
+        )
+        .unwrap()
+        .with_deadline(Instant::now());
+
+        export_into(&configuration, &slack, &output).unwrap();
+
+        assert!(slack.reached_deadline());
+        assert_eq!(spilled_files(directory.path()), Vec::<PathBuf>::new());
+
+        // the partial export is still written out in full, oldest first
+        let messages: Vec<serde_json::Value> =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        let ts: Vec<_> = messages.iter().map(|message| &message["ts"]).collect();
+        assert_eq!(ts, ["1.000000", "2.000000", "3.000000"]);
+    }
+
+    #[test]
+    fn removes_spilled_runs_when_an_export_fails_partway() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("export.json");
+        let configuration = spilling(directory.path());
+
+        let exported = export_to(
+            &configuration,
+            vec![
+                page(&["3.000000", "2.000000"], Some("page-2")),
+                serde_json::json!({ "ok": false, "error": "channel_not_found" }),
+            ],
+            &output,
+        );
+
+        assert!(exported.is_err());
+        assert_eq!(spilled_files(directory.path()), Vec::<PathBuf>::new());
+    }
+
+    /// Time writing a large export through output buffers of a few sizes, e.g. to pick a
+    /// `BUFFER_BYTES` for a disk (run with `cargo test --release -- --ignored --nocapture`)
+    #[test]
+    #[ignore]
+    fn times_buffer_sizes() {
+        let messages: Vec<_> = (0..200_000)
+            .map(|index| {
+                serde_json::json!({
+                    "type": "message",
+                    "user": "U0123ABCD",
+                    "text": format!("Message {} with a typical amount of text: {}", index, "lorem ipsum ".repeat(12)),
+                    "ts": format!("{}.000100", 1_700_000_000 + index),
+                    "reactions": [{ "name": "eyes", "count": 1, "users": ["U0123ABCD"] }],
+                })
+            })
+            .collect();
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("export.json");
+
+        for buffer_bytes in [DEFAULT_BUFFER_BYTES, 64 * 1024, 256 * 1024, 1024 * 1024] {
+            // the best of a few runs, to leave out the noise of other work on the machine
+            let elapsed = (0..3)
+                .map(|_| {
+                    let started = Instant::now();
+                    let out = Compression::None
+                        .wrap(File::create(&path).unwrap(), None)
+                        .unwrap();
+                    let mut sink = ArrayWriter::new(BufWriter::with_capacity(buffer_bytes, out));
+
+                    for message in &messages {
+                        sink.write(message).unwrap();
+                    }
+
+                    sink.finish().unwrap();
+                    started.elapsed()
+                })
+                .min()
+                .unwrap();
+
+            eprintln!(
+                "BUFFER_BYTES={}: {} messages ({} MB) in {:?}",
+                buffer_bytes,
+                messages.len(),
+                fs::metadata(&path).unwrap().len() / 1_000_000,
+                elapsed
+            );
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use super::dates;
+use serde::Serialize;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// Name of the manifest written alongside the files of a multi-file export
+pub static MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Description of a single file in a multi-file export, for downstream loaders
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    file: PathBuf,
+    channel: String,
+    messages: u64,
+    oldest_ts: Option<String>,
+    latest_ts: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Start describing a file (relative to the manifest) holding messages from a channel
+    pub fn new(file: impl Into<PathBuf>, channel: &str) -> Self {
+        Self {
+            file: file.into(),
+            channel: channel.into(),
+            messages: 0,
+            oldest_ts: None,
+            latest_ts: None,
+        }
+    }
+
+    /// Record a message written to the file, widening its range of timestamps
+    pub fn record(&mut self, message: &serde_json::Value) {
+        self.messages += 1;
+
+        let ts = match message.get("ts").and_then(serde_json::Value::as_str) {
+            Some(ts) => ts,
+            None => return,
+        };
+        let datetime = dates::from_slack_timestamp(ts);
+        let is_later = |other: &Option<String>| {
+            other.as_deref().and_then(dates::from_slack_timestamp) > datetime
+        };
+
+        if self.oldest_ts.is_none() || is_later(&self.oldest_ts) {
+            self.oldest_ts = Some(ts.into());
+        }
+
+        if self.latest_ts.is_none() || !is_later(&self.latest_ts) {
+            self.latest_ts = Some(ts.into());
+        }
+    }
+}
+
+/// Write the entries of a multi-file export as a JSON array, in the order they were written
+pub fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> anyhow::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, entries)?;
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use super::dates;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind},
+    path::Path,
+};
+
+/// Messages from a previous export, merged into a new export of the messages sent since
+#[derive(Debug, Default)]
+pub struct Seed {
+    messages: Vec<serde_json::Value>,
+    timestamps: HashSet<String>,
+}
+
+impl Seed {
+    /// Read a previous export written as a single JSON array of messages
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .map_err(|error| anyhow::anyhow!("Error opening {}: {}", path.display(), error))?;
+        let messages: Vec<serde_json::Value> = serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| {
+                anyhow::anyhow!(
+                    "Error reading {} as an array of messages: {}",
+                    path.display(),
+                    error
+                )
+            })?;
+        let timestamps = messages.iter().filter_map(ts).map(String::from).collect();
+
+        Ok(Self {
+            messages,
+            timestamps,
+        })
+    }
+
+    /// Timestamp of the newest message in the previous export, if it has any messages
+    pub fn newest_ts(&self) -> Option<&str> {
+        self.timestamps
+            .iter()
+            .filter_map(|ts| Some((dates::from_slack_timestamp(ts)?, ts)))
+            .max()
+            .map(|(_, ts)| ts.as_str())
+    }
+
+    /// Check whether a message was already part of the previous export
+    pub fn contains(&self, message: &serde_json::Value) -> bool {
+        ts(message).is_some_and(|ts| self.timestamps.contains(ts))
+    }
+
+    /// The messages of the previous export, in their original order
+    pub fn messages(&self) -> &[serde_json::Value] {
+        &self.messages
+    }
+}
+
+/// Find the `ts` of the newest top-level message in a previous export, to continue from it
+///
+/// Arrays are written newest first, so only their first message is parsed. NDJSON written by
+/// follow mode has newer messages appended to the end, so every line is scanned (without keeping
+/// any of them in memory). A missing or empty file has no newest message.
+pub fn newest_ts_in(path: &Path) -> anyhow::Result<Option<String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => anyhow::bail!("Error opening {}: {}", path.display(), error),
+    };
+    let mut reader = BufReader::new(file);
+    let invalid = |error: serde_json::Error| {
+        anyhow::anyhow!(
+            "Error reading the newest message in {}: {}",
+            path.display(),
+            error
+        )
+    };
+
+    // tell an array from NDJSON by its first character
+    match peek_past_whitespace(&mut reader)? {
+        None => return Ok(None),
+        Some(b'[') => {
+            reader.consume(1);
+
+            // an empty array has no first message to parse
+            if peek_past_whitespace(&mut reader)? == Some(b']') {
+                return Ok(None);
+            }
+
+            let message = serde_json::Deserializer::from_reader(reader)
+                .into_iter::<Timestamps>()
+                .next()
+                .transpose()
+                .map_err(invalid)?;
+
+            return Ok(message.and_then(Timestamps::top_level_ts));
+        }
+        Some(_) => {}
+    }
+
+    let mut newest: Option<String> = None;
+
+    for message in serde_json::Deserializer::from_reader(reader).into_iter::<Timestamps>() {
+        let ts = match message.map_err(invalid)?.top_level_ts() {
+            Some(ts) => ts,
+            None => continue,
+        };
+
+        let is_newer = newest.as_deref().is_none_or(|newest| {
+            dates::from_slack_timestamp(&ts) > dates::from_slack_timestamp(newest)
+        });
+
+        if is_newer {
+            newest = Some(ts);
+        }
+    }
+
+    Ok(newest)
+}
+
+/// Skip whitespace, returning the next byte without consuming it (or nothing at the end)
+fn peek_past_whitespace(reader: &mut impl BufRead) -> std::io::Result<Option<u8>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(position) => {
+                let next = buffer[position];
+                reader.consume(position);
+
+                return Ok(Some(next));
+            }
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    }
+}
+
+/// The timestamps of a message in a previous export, ignoring every other field
+#[derive(Deserialize)]
+struct Timestamps {
+    ts: Option<String>,
+    thread_ts: Option<String>,
+    subtype: Option<String>,
+}
+
+impl Timestamps {
+    /// The message's `ts`, unless it's a reply that only appears in its thread (and so isn't
+    /// part of the conversation history that the next export continues)
+    fn top_level_ts(self) -> Option<String> {
+        let is_broadcast = self.subtype.as_deref() == Some("thread_broadcast");
+
+        match self.thread_ts {
+            Some(thread_ts) if Some(&thread_ts) != self.ts.as_ref() && !is_broadcast => None,
+            _ => self.ts,
+        }
+    }
+}
+
+/// The `ts` field of a message
+fn ts(message: &serde_json::Value) -> Option<&str> {
+    message.get("ts").and_then(serde_json::Value::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Write a previous export to a temporary file
+    fn export(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    /// The newest `ts` in a previous export with these contents
+    fn newest(contents: &str) -> Option<String> {
+        newest_ts_in(export(contents).path()).unwrap()
+    }
+
+    #[test]
+    fn reads_the_first_message_of_an_array() {
+        let array = r#"[{"ts":"1700000003.000000"},{"ts":"1700000009.000000"}]"#;
+        let pretty = "\n  [\n  {\n    \"ts\": \"1700000003.000000\"\n  }\n]\n";
+
+        assert_eq!(newest(array).as_deref(), Some("1700000003.000000"));
+        assert_eq!(newest(pretty).as_deref(), Some("1700000003.000000"));
+    }
+
+    #[test]
+    fn scans_every_line_of_ndjson() {
+        let ndjson = [
+            r#"{"ts":"999999999.000000"}"#,
+            r#"{"ts":"1000000000.000000"}"#,
+            r#"{"ts":"1000000005.000000","thread_ts":"1000000000.000000"}"#,
+            r#"{"ts":"1000000001.000000","thread_ts":"1000000000.000000","subtype":"thread_broadcast"}"#,
+            r#"{"ts":"999999998.000000"}"#,
+        ]
+        .join("\n");
+
+        // timestamps are compared as numbers, and replies only in their thread are skipped
+        assert_eq!(newest(&ndjson).as_deref(), Some("1000000001.000000"));
+    }
+
+    #[test]
+    fn has_no_newest_message_without_messages() {
+        assert_eq!(newest(""), None);
+        assert_eq!(newest(" \n"), None);
+        assert_eq!(newest("[ ]"), None);
+        assert_eq!(
+            newest_ts_in(Path::new("/nonexistent/slump-export.json")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_exports() {
+        for contents in ["[{\"ts\":", "{\"ts\":\"1.000000\"}\nnot json"] {
+            let error = newest_ts_in(export(contents).path()).unwrap_err();
+
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("Error reading the newest message"),
+                "{}",
+                error
+            );
+        }
+    }
+}
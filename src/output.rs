@@ -0,0 +1,1124 @@
+use super::{write_manifest, ManifestEntry, MANIFEST_FILE_NAME};
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use serde_json::ser::PrettyFormatter;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, ErrorKind, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// Format used to write exported messages
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// A single JSON array of messages
+    #[default]
+    Array,
+    /// One JSON object per line (newline-delimited JSON), e.g. for `jq -c` or BigQuery
+    Ndjson,
+    /// One JSON object per message, each followed by a NUL byte (e.g. for `xargs -0`)
+    Nul,
+    /// An Apache Parquet file with one column per common message field
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// An SQLite database with a `messages` table, holding each message's full JSON
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl OutputFormat {
+    /// File extension for exports written in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Array => "json",
+            Self::Ndjson => "ndjson",
+            Self::Nul => "json0",
+            #[cfg(feature = "parquet")]
+            Self::Parquet => "parquet",
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite => "db",
+        }
+    }
+
+    /// Format of exports written with a file extension, if it's one of the formats' own
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "json" => Some(Self::Array),
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            "json0" => Some(Self::Nul),
+            #[cfg(feature = "parquet")]
+            "parquet" => Some(Self::Parquet),
+            #[cfg(feature = "sqlite")]
+            "db" | "sqlite" | "sqlite3" => Some(Self::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Split a combined extension like `json.gz` into the format and the compression it names
+    pub fn from_combined_extension(extension: &str) -> Option<(Self, Compression)> {
+        let (extension, compression) = Compression::split_suffix(extension);
+
+        Some((Self::from_extension(extension)?, compression))
+    }
+
+    /// Format and compression implied by the name of an output file (e.g. `export.json.gz`)
+    pub fn infer_from_path(path: &Path) -> Option<(Self, Compression)> {
+        let name = path.file_name()?.to_str()?;
+
+        // only the last extension before any compression suffix names the format
+        let (name, compression) = Compression::split_suffix(name);
+
+        let extension = Path::new(name).extension()?.to_str()?;
+
+        Some((Self::from_extension(extension)?, compression))
+    }
+}
+
+/// Whether JSON array output is strict, or ends every message with a comma for easy appending
+///
+/// Only `None` produces strict JSON. The others are valid JSON5 (and JSONC), and `Bare` output from
+/// several runs can be concatenated into a single list.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingCommas {
+    /// A strict JSON array, with commas only between messages: `[m1,m2]`
+    #[default]
+    None,
+    /// An array with a comma after every message, including the last: `[m1,m2,]`
+    Array,
+    /// No brackets, with a comma and a newline after every message: `m1,\nm2,\n`
+    Bare,
+}
+
+/// How to open an output file that already exists
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExisting {
+    /// Refuse to touch the existing file
+    #[default]
+    Fail,
+    /// Replace the contents of the existing file
+    Overwrite,
+    /// Add to the end of the existing file (only valid for NDJSON output)
+    Append,
+}
+
+impl OnExisting {
+    /// Open an output file for writing according to the policy
+    pub fn open(self, path: &Path) -> anyhow::Result<File> {
+        let mut options = OpenOptions::new();
+
+        match self {
+            Self::Fail => options.write(true).create_new(true),
+            Self::Overwrite => options.write(true).create(true).truncate(true),
+            Self::Append => options.append(true).create(true),
+        };
+
+        options.open(path).map_err(|error| match error.kind() {
+            ErrorKind::AlreadyExists => anyhow::anyhow!(
+                "Output file {} already exists: set ON_EXISTING to overwrite or append to it",
+                path.display()
+            ),
+            _ => anyhow::anyhow!("Error opening {}: {}", path.display(), error),
+        })
+    }
+}
+
+/// Most files that a `UserSplitter` keeps open at once, well below the usual limit of 1024 file
+/// descriptors per process
+static MAX_OPEN_USER_FILES: usize = 256;
+
+/// Name of the file (without its extension) for messages that have neither a user nor a bot
+static UNKNOWN_USER: &str = "unknown";
+
+/// Compression applied to the output as it's written
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Write the output as is
+    #[default]
+    None,
+    /// Compress with gzip, which almost anything can read
+    Gzip,
+    /// Compress with Zstandard, which is smaller and faster than gzip for Slack's JSON
+    Zstd,
+}
+
+impl Compression {
+    /// Suffix added to the extension of files compressed this way (e.g. `.zst`)
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Zstd => ".zst",
+        }
+    }
+
+    /// Split the suffix of a compression off a file name or extension, if it ends with one
+    pub fn split_suffix(name: &str) -> (&str, Self) {
+        [Self::Gzip, Self::Zstd]
+            .into_iter()
+            .find_map(|compression| {
+                name.strip_suffix(compression.suffix())
+                    .map(|name| (name, compression))
+            })
+            .unwrap_or((name, Self::None))
+    }
+
+    /// Levels accepted by the compression, if it has any
+    pub fn levels(self) -> Option<RangeInclusive<i32>> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some(0..=9),
+            Self::Zstd => Some(zstd::compression_level_range()),
+        }
+    }
+
+    /// Compress everything written to a writer, at the provided level or the default one
+    ///
+    /// The compressed stream is only complete once it's finished with [`Finish::try_finish`],
+    /// which every sink does as it finishes.
+    pub fn wrap<W: Write + Send + 'static>(
+        self,
+        writer: W,
+        level: Option<i32>,
+    ) -> anyhow::Result<Compressed> {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        let encoder = match self {
+            Self::None => Encoder::None(writer),
+            Self::Gzip => {
+                let level = level
+                    .and_then(|level| u32::try_from(level).ok())
+                    .map_or_else(flate2::Compression::default, flate2::Compression::new);
+
+                Encoder::Gzip(GzEncoder::new(writer, level))
+            }
+            Self::Zstd => {
+                let level = level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+
+                Encoder::Zstd(zstd::Encoder::new(writer, level)?)
+            }
+        };
+
+        Ok(Compressed { encoder })
+    }
+}
+
+/// Writer that's only complete once it's finished, e.g. a compressed stream and its trailer
+pub trait Finish: Write {
+    /// Flush everything written so far, and end the stream
+    fn try_finish(&mut self) -> io::Result<()>;
+}
+
+impl Finish for Vec<u8> {
+    fn try_finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Finish for File {
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Finish for io::Stdout {
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Finish> Finish for BufWriter<W> {
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().try_finish()
+    }
+}
+
+impl<W: Finish + ?Sized> Finish for &mut W {
+    fn try_finish(&mut self) -> io::Result<()> {
+        (**self).try_finish()
+    }
+}
+
+/// Output compressed by [`Compression::wrap`]
+pub struct Compressed {
+    encoder: Encoder,
+}
+
+/// Encoder behind a [`Compressed`] output, kept concrete so that its stream can be finished
+enum Encoder {
+    None(Box<dyn Write + Send>),
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write + Send>>),
+}
+
+impl Write for Compressed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.encoder {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl Finish for Compressed {
+    fn try_finish(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => {
+                encoder.try_finish()?;
+                encoder.get_mut().flush()
+            }
+            Encoder::Zstd(encoder) => {
+                encoder.do_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Drop for Compressed {
+    fn drop(&mut self) {
+        // a stream that was never finished (e.g. after an error) is still ended, as best it can be
+        let _ = self.try_finish();
+    }
+}
+
+/// Destination for exported messages
+pub trait Sink {
+    /// Write a single message
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()>;
+
+    /// Flush any buffered output to the underlying writer
+    fn flush(&mut self) -> anyhow::Result<()>;
+
+    /// Complete the output once every message has been written
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        (**self).write(message)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        (**self).flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Writes every message to several sinks, e.g. to export in more than one format at once
+///
+/// Each message reaches every sink as soon as it's written, so every output is streamed.
+pub struct FanOut {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl FanOut {
+    /// Create a sink writing to each of the provided sinks, in order
+    pub fn new(sinks: Vec<Box<dyn Sink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl Sink for FanOut {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        for sink in &mut self.sinks {
+            sink.write(message)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        for sink in &mut self.sinks {
+            sink.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        // close every output even if one fails, reporting the first failure
+        self.sinks
+            .iter_mut()
+            .map(|sink| sink.finish())
+            .fold(Ok(()), Result::and)
+    }
+}
+
+/// Writes messages as a single JSON array
+///
+/// Separators only depend on how many messages have been written so far, never on the pages
+/// they arrived in, so page boundaries (including empty pages) can't misplace a comma.
+pub struct ArrayWriter<W> {
+    out: W,
+    messages: u64,
+    indent: Option<Vec<u8>>,
+    depth: usize,
+    trailing_commas: TrailingCommas,
+}
+
+impl<W: Write> ArrayWriter<W> {
+    /// Create an array writer, opening the array with the first message
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            messages: 0,
+            indent: None,
+            depth: 0,
+            trailing_commas: TrailingCommas::None,
+        }
+    }
+
+    /// Pretty-print the array, indenting each level by a number of spaces
+    pub fn with_indent(mut self, indent: Option<usize>) -> Self {
+        self.indent = indent.map(|width| vec![b' '; width]);
+        self
+    }
+
+    /// Follow every message with a comma instead of only separating them, e.g. for appending
+    pub fn with_trailing_commas(mut self, trailing_commas: TrailingCommas) -> Self {
+        self.trailing_commas = trailing_commas;
+        self
+    }
+
+    /// Continue an array that an earlier writer left open, e.g. when resuming an interrupted export
+    ///
+    /// The array is only opened along with its first message, so any earlier output at all means
+    /// that the next message needs a separator rather than the opening bracket.
+    pub fn continuing(mut self, has_messages: bool) -> Self {
+        self.messages = u64::from(has_messages);
+        self
+    }
+}
+
+impl<W: Finish> Sink for ArrayWriter<W> {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        // open the array before the first message, and separate every message after that
+        match self.trailing_commas {
+            TrailingCommas::None => {
+                let separator = if self.messages == 0 { b"[" } else { b"," };
+                self.out.write_all(separator)?;
+            }
+            TrailingCommas::Array if self.messages == 0 => self.out.write_all(b"[")?,
+            TrailingCommas::Array | TrailingCommas::Bare => {}
+        }
+
+        // bare messages aren't nested in an array, so they aren't indented either
+        let depth = match self.trailing_commas {
+            TrailingCommas::Bare => self.depth,
+            TrailingCommas::None | TrailingCommas::Array => {
+                write_newline(&mut self.out, self.indent.as_deref(), self.depth + 1)?;
+                self.depth + 1
+            }
+        };
+
+        // serialize straight into the writer rather than an intermediate buffer,
+        // so that a single oversized message never inflates memory for the rest of the export
+        write_value(&mut self.out, message, self.indent.as_deref(), depth)?;
+        self.messages += 1;
+
+        match self.trailing_commas {
+            TrailingCommas::None => {}
+            TrailingCommas::Array => self.out.write_all(b",")?,
+            TrailingCommas::Bare => self.out.write_all(b",\n")?,
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.close()?;
+        self.out.try_finish()?;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> ArrayWriter<W> {
+    /// Close the array without flushing the underlying writer
+    fn close(&mut self) -> std::io::Result<()> {
+        // bare output is never closed, so that more messages can always be appended to it
+        if self.trailing_commas == TrailingCommas::Bare {
+            return Ok(());
+        }
+
+        if self.messages == 0 {
+            self.out.write_all(b"[")?;
+        } else {
+            write_newline(&mut self.out, self.indent.as_deref(), self.depth)?;
+        }
+
+        self.out.write_all(b"]")
+    }
+}
+
+/// Writes each message as a compact JSON object followed by a delimiter, like NDJSON but with any
+/// delimiter (e.g. a NUL byte)
+///
+/// JSON escapes every control character inside strings, so the delimiter can be any byte that
+/// isn't otherwise part of compact JSON without ever appearing within a message.
+pub struct DelimitedWriter<W> {
+    out: W,
+    delimiter: &'static [u8],
+}
+
+impl<W: Write> DelimitedWriter<W> {
+    /// Create a writer ending every message with the provided delimiter
+    pub fn new(out: W, delimiter: &'static [u8]) -> Self {
+        Self { out, delimiter }
+    }
+}
+
+impl<W: Finish> Sink for DelimitedWriter<W> {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.out, message)?;
+        self.out.write_all(self.delimiter)?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.out.try_finish()?;
+
+        Ok(())
+    }
+}
+
+/// Writes messages as an array under a key (e.g. `messages`) in a JSON object, after other fields
+pub struct EnvelopeWriter<W> {
+    messages: ArrayWriter<W>,
+}
+
+impl<W: Write> EnvelopeWriter<W> {
+    /// Create an envelope writer, writing the other top-level fields immediately
+    ///
+    /// Providing an indent pretty-prints the whole object, indenting each level by that many
+    /// spaces.
+    pub fn new(
+        mut out: W,
+        fields: &serde_json::Map<String, serde_json::Value>,
+        messages_key: &str,
+        indent: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        let indent = indent.map(|width| vec![b' '; width]);
+        let colon: &[u8] = if indent.is_some() { b": " } else { b":" };
+
+        out.write_all(b"{")?;
+
+        for (key, value) in fields {
+            write_newline(&mut out, indent.as_deref(), 1)?;
+            serde_json::to_writer(&mut out, key)?;
+            out.write_all(colon)?;
+            write_value(&mut out, value, indent.as_deref(), 1)?;
+            out.write_all(b",")?;
+        }
+
+        write_newline(&mut out, indent.as_deref(), 1)?;
+        serde_json::to_writer(&mut out, messages_key)?;
+        out.write_all(colon)?;
+
+        Ok(Self {
+            messages: ArrayWriter {
+                out,
+                messages: 0,
+                indent,
+                depth: 1,
+                trailing_commas: TrailingCommas::None,
+            },
+        })
+    }
+}
+
+impl<W: Finish> Sink for EnvelopeWriter<W> {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        self.messages.write(message)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.messages.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.messages.close()?;
+        write_newline(&mut self.messages.out, self.messages.indent.as_deref(), 0)?;
+        self.messages.out.write_all(b"}")?;
+        self.messages.out.try_finish()?;
+
+        Ok(())
+    }
+}
+
+/// Writes each thread to its own file in a directory, named after the thread's `thread_ts`
+///
+/// Messages outside of threads are written to a `main.json` file in the same directory.
+/// This relies on replies directly following their parent message, as they do when
+/// replies are included in the export.
+pub struct ThreadSplitter {
+    directory: PathBuf,
+    channel: String,
+    indent: Option<usize>,
+    main: ArrayWriter<BufWriter<File>>,
+    thread: Option<(String, ArrayWriter<BufWriter<File>>)>,
+    manifest: Vec<ManifestEntry>,
+}
+
+impl ThreadSplitter {
+    /// Create the output directory and its `main.json` file for a channel's messages, optionally
+    /// pretty-printing every file with an indent
+    pub fn create(directory: &Path, channel: &str, indent: Option<usize>) -> anyhow::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        let main = File::create(directory.join("main.json"))?;
+
+        Ok(Self {
+            directory: directory.into(),
+            channel: channel.into(),
+            indent,
+            main: ArrayWriter::new(BufWriter::new(main)).with_indent(indent),
+            thread: None,
+            manifest: vec![ManifestEntry::new("main.json", channel)],
+        })
+    }
+
+    /// Finish the file for the current thread, if there is one
+    fn finish_thread(&mut self) -> anyhow::Result<()> {
+        if let Some((_, mut thread)) = self.thread.take() {
+            thread.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for ThreadSplitter {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        let ts = message.get("ts").and_then(serde_json::Value::as_str);
+        let thread_ts = message.get("thread_ts").and_then(serde_json::Value::as_str);
+
+        match thread_ts {
+            // thread parents start a new thread file
+            Some(thread_ts) if Some(thread_ts) == ts => {
+                self.finish_thread()?;
+
+                let file_name = format!("{}.json", thread_ts);
+                let file = File::create(self.directory.join(&file_name))?;
+                let mut thread = ArrayWriter::new(BufWriter::new(file)).with_indent(self.indent);
+                thread.write(message)?;
+
+                let mut entry = ManifestEntry::new(file_name, &self.channel);
+                entry.record(message);
+                self.manifest.push(entry);
+
+                self.thread = Some((thread_ts.into(), thread));
+
+                Ok(())
+            }
+            // replies that immediately follow their parent belong to the current thread
+            Some(thread_ts)
+                if self
+                    .thread
+                    .as_ref()
+                    .is_some_and(|(current, _)| current == thread_ts) =>
+            {
+                let (_, thread) = self.thread.as_mut().expect("current thread exists");
+                thread.write(message)?;
+
+                if let Some(entry) = self.manifest.last_mut() {
+                    entry.record(message);
+                }
+
+                Ok(())
+            }
+            // everything else (including replies also sent to the channel) is a top-level message
+            _ => {
+                self.main.write(message)?;
+                self.manifest[0].record(message);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some((_, thread)) = &mut self.thread {
+            thread.flush()?;
+        }
+
+        self.main.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.finish_thread()?;
+        self.main.finish()?;
+
+        // describe the files once they're all complete
+        write_manifest(&self.directory.join(MANIFEST_FILE_NAME), &self.manifest)
+    }
+}
+
+/// Writes each user's messages to their own NDJSON file in a directory, named after their ID
+///
+/// Messages are routed on their `user`, or their `bot_id` if they have no user, and messages with
+/// neither are written to `unknown.ndjson`. Channels can have more users than there are file
+/// descriptors, so once too many files are open they're all closed, and reopened for appending as
+/// their users come up again.
+pub struct UserSplitter {
+    directory: PathBuf,
+    channel: String,
+    open: HashMap<String, BufWriter<File>>,
+    manifest: BTreeMap<String, ManifestEntry>,
+}
+
+impl UserSplitter {
+    /// Create the output directory for a channel's messages
+    pub fn create(directory: &Path, channel: &str) -> anyhow::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        Ok(Self {
+            directory: directory.into(),
+            channel: channel.into(),
+            open: HashMap::new(),
+            manifest: BTreeMap::new(),
+        })
+    }
+
+    /// Find the open file for a user's messages, opening it if needed
+    fn file_for(&mut self, user: &str) -> anyhow::Result<&mut BufWriter<File>> {
+        if !self.open.contains_key(user) {
+            if self.open.len() >= MAX_OPEN_USER_FILES {
+                self.close_all()?;
+            }
+
+            let file_name = format!("{}.ndjson", user);
+            let path = self.directory.join(&file_name);
+
+            // a user's file is replaced the first time they come up, and appended to afterwards
+            let file = if self.manifest.contains_key(user) {
+                OpenOptions::new().append(true).open(path)?
+            } else {
+                self.manifest
+                    .insert(user.into(), ManifestEntry::new(file_name, &self.channel));
+
+                File::create(path)?
+            };
+
+            self.open.insert(user.into(), BufWriter::new(file));
+        }
+
+        Ok(self.open.get_mut(user).expect("user's file is open"))
+    }
+
+    /// Flush and close every open file
+    fn close_all(&mut self) -> anyhow::Result<()> {
+        for (_, mut file) in self.open.drain() {
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for UserSplitter {
+    fn write(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        let user = user_file_stem(message);
+        let out = self.file_for(&user)?;
+
+        serde_json::to_writer(&mut *out, message)?;
+        out.write_all(b"\n")?;
+
+        if let Some(entry) = self.manifest.get_mut(&user) {
+            entry.record(message);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        for file in self.open.values_mut() {
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.close_all()?;
+
+        // describe the files once they're all complete, in order of user
+        let manifest: Vec<_> = std::mem::take(&mut self.manifest).into_values().collect();
+
+        write_manifest(&self.directory.join(MANIFEST_FILE_NAME), &manifest)
+    }
+}
+
+/// Name of the file (without its extension) that a message belongs in, from its user or bot ID
+fn user_file_stem(message: &serde_json::Value) -> String {
+    let id = ["user", "bot_id"]
+        .into_iter()
+        .find_map(|key| message.get(key).and_then(serde_json::Value::as_str))
+        .filter(|id| !id.is_empty());
+
+    match id {
+        // IDs are alphanumeric, but anything else mustn't be able to escape the directory
+        Some(id) => id
+            .chars()
+            .map(|character| match character {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => character,
+                _ => '_',
+            })
+            .collect(),
+        None => UNKNOWN_USER.into(),
+    }
+}
+
+/// Serialize a value nested `depth` levels deep, pretty-printing it when an indent is provided
+fn write_value<W: Write>(
+    out: &mut W,
+    value: &serde_json::Value,
+    indent: Option<&[u8]>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    match indent {
+        Some(indent) => {
+            let out = Nested {
+                out,
+                prefix: indent.repeat(depth),
+            };
+            let mut serializer =
+                serde_json::Serializer::with_formatter(out, PrettyFormatter::with_indent(indent));
+            value.serialize(&mut serializer)?;
+        }
+        None => serde_json::to_writer(out, value)?,
+    }
+
+    Ok(())
+}
+
+/// Start a new line indented `depth` levels deep, when pretty-printing
+fn write_newline<W: Write>(out: &mut W, indent: Option<&[u8]>, depth: usize) -> io::Result<()> {
+    if let Some(indent) = indent {
+        out.write_all(b"\n")?;
+        out.write_all(&indent.repeat(depth))?;
+    }
+
+    Ok(())
+}
+
+/// Writer that indents every line after the first, for pretty-printing nested values
+struct Nested<'a, W> {
+    out: &'a mut W,
+    prefix: Vec<u8>,
+}
+
+impl<W: Write> Write for Nested<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // JSON escapes newlines within strings, so every newline written here is between tokens
+        for line in buf.split_inclusive(|byte| *byte == b'\n') {
+            self.out.write_all(line)?;
+
+            if line.ends_with(b"\n") {
+                self.out.write_all(&self.prefix)?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Write pages of messages through an array writer, flushing at every page boundary as an
+    /// export does
+    fn write_pages(pages: &[Vec<serde_json::Value>], indent: Option<usize>) -> Vec<u8> {
+        let mut sink = ArrayWriter::new(Vec::new()).with_indent(indent);
+
+        for page in pages {
+            for message in page {
+                sink.write(message).unwrap();
+            }
+
+            sink.flush().unwrap();
+        }
+
+        sink.finish().unwrap();
+        sink.out
+    }
+
+    #[test]
+    fn separates_messages_across_pages() {
+        // a non-terminal page, an empty non-terminal page and the terminal page
+        let pages = [
+            vec![json!({ "ts": "3" }), json!({ "ts": "2" })],
+            vec![],
+            vec![json!({ "ts": "1" })],
+        ];
+
+        assert_eq!(
+            write_pages(&pages, None),
+            br#"[{"ts":"3"},{"ts":"2"},{"ts":"1"}]"#
+        );
+
+        for indent in [None, Some(2)] {
+            let parsed: Vec<serde_json::Value> =
+                serde_json::from_slice(&write_pages(&pages, indent)).unwrap();
+
+            assert_eq!(parsed, pages.concat());
+        }
+    }
+
+    #[test]
+    fn separates_messages_after_an_empty_first_page() {
+        let pages = [vec![], vec![json!({ "ts": "2" }), json!({ "ts": "1" })]];
+
+        assert_eq!(write_pages(&pages, None), br#"[{"ts":"2"},{"ts":"1"}]"#);
+    }
+
+    #[test]
+    fn closes_an_array_without_messages() {
+        assert_eq!(write_pages(&[vec![], vec![]], None), b"[]");
+    }
+
+    #[test]
+    fn continues_an_array_left_open() {
+        let mut output = br#"[{"ts":"3"}"#.to_vec();
+
+        let mut array = ArrayWriter::new(&mut output).continuing(true);
+        array.write(&json!({ "ts": "2" })).unwrap();
+        array.finish().unwrap();
+
+        assert_eq!(output, br#"[{"ts":"3"},{"ts":"2"}]"#);
+
+        // nothing written yet means the array was never opened
+        let mut output = Vec::new();
+
+        let mut array = ArrayWriter::new(&mut output).continuing(false);
+        array.write(&json!({ "ts": "1" })).unwrap();
+        array.finish().unwrap();
+
+        assert_eq!(output, br#"[{"ts":"1"}]"#);
+    }
+
+    #[test]
+    fn writes_compact_json_with_numbers_as_slack_sent_them() {
+        let message: serde_json::Value = serde_json::from_str(
+            r#"{ "big" : 123456789012345678901234567890, "exponent": 1e+400,
+                "nested" : { "list" : [ 1.50, -0.0 , 0.1000000000000000055511151231257827 ] },
+                "text" : "a b\tc" }"#,
+        )
+        .unwrap();
+        let compact = r#"{"big":123456789012345678901234567890,"exponent":1e+400,"nested":{"list":[1.50,-0.0,0.1000000000000000055511151231257827]},"text":"a b\tc"}"#;
+
+        let mut array = ArrayWriter::new(Vec::new());
+        array.write(&message).unwrap();
+        array.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(array.out).unwrap(),
+            format!("[{}]", compact)
+        );
+
+        let mut ndjson = DelimitedWriter::new(Vec::new(), b"\n");
+        ndjson.write(&message).unwrap();
+        ndjson.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(ndjson.out).unwrap(),
+            format!("{}\n", compact)
+        );
+    }
+
+    /// Write an envelope with custom emoji listed in the provided order, and a single message
+    fn write_envelope(emoji: &[(&str, &str)]) -> Vec<u8> {
+        let emoji: HashMap<String, String> = emoji
+            .iter()
+            .map(|(name, url)| (name.to_string(), url.to_string()))
+            .collect();
+        let mut fields = serde_json::Map::new();
+        fields.insert("members".into(), json!(["U2", "U1"]));
+        fields.insert("emoji".into(), emoji.into_iter().collect());
+        fields.insert("channel".into(), json!({ "name": "general", "id": "C1" }));
+
+        let mut sink = EnvelopeWriter::new(Vec::new(), &fields, "messages", None).unwrap();
+        sink.write(&json!({ "user": "U1", "ts": "1", "text": "hi" }))
+            .unwrap();
+        sink.finish().unwrap();
+
+        sink.messages.out
+    }
+
+    #[test]
+    fn writes_envelopes_in_a_stable_order() {
+        let emoji = [("zebra", "z.png"), ("apple", "a.png"), ("mango", "m.png")];
+        let mut reversed = emoji;
+        reversed.reverse();
+
+        let written = write_envelope(&emoji);
+
+        assert_eq!(written, write_envelope(&reversed));
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            r#"{"channel":{"id":"C1","name":"general"},"emoji":{"apple":"a.png","mango":"m.png","zebra":"z.png"},"members":["U2","U1"],"messages":[{"text":"hi","ts":"1","user":"U1"}]}"#
+        );
+    }
+
+    /// Output that keeps everything written to it, along with the largest single write
+    #[derive(Default)]
+    struct LargestWrite {
+        written: Vec<u8>,
+        largest: usize,
+    }
+
+    impl Write for LargestWrite {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.largest = self.largest.max(buf.len());
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Finish for LargestWrite {
+        fn try_finish(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_multi_megabyte_messages_without_buffering_them() {
+        let blocks: Vec<_> = (0..50_000)
+            .map(|index| json!({ "type": "section", "text": format!("block number {}", index) }))
+            .collect();
+        let huge = json!({ "ts": "2", "blocks": blocks });
+        let small = json!({ "ts": "1" });
+
+        for indent in [None, Some(2)] {
+            let mut sink = ArrayWriter::new(LargestWrite::default()).with_indent(indent);
+
+            sink.write(&huge).unwrap();
+            sink.write(&small).unwrap();
+            sink.finish().unwrap();
+
+            // the message is serialized piece by piece rather than into a buffer of its own
+            let out = sink.out;
+            assert!(out.written.len() > 2_000_000, "{}", out.written.len());
+            assert!(out.largest < 1024, "{}", out.largest);
+
+            let parsed: Vec<serde_json::Value> = serde_json::from_slice(&out.written).unwrap();
+            assert_eq!(parsed, [huge.clone(), small.clone()]);
+        }
+    }
+
+    /// Output shared with the test, so that it can be read before the writer is dropped
+    #[derive(Clone, Default)]
+    struct SharedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Output that accepts nothing, like a full disk
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("no space left on device"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finishes_compressed_streams_with_the_sink() {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let output = SharedOutput::default();
+            let out = compression.wrap(output.clone(), None).unwrap();
+            let mut sink = ArrayWriter::new(BufWriter::new(out));
+
+            sink.write(&json!({ "ts": "1" })).unwrap();
+            sink.finish().unwrap();
+
+            // the whole stream is readable while the sink is still alive
+            let compressed = output.0.lock().unwrap().clone();
+            let mut decompressed = Vec::new();
+
+            match compression {
+                Compression::Gzip => {
+                    io::Read::read_to_end(
+                        &mut flate2::read::GzDecoder::new(&compressed[..]),
+                        &mut decompressed,
+                    )
+                    .unwrap();
+                }
+                _ => decompressed = zstd::decode_all(&compressed[..]).unwrap(),
+            }
+
+            assert_eq!(decompressed, br#"[{"ts":"1"}]"#, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn reports_errors_finishing_compressed_streams() {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let out = compression.wrap(FullDisk, None).unwrap();
+            let mut sink = DelimitedWriter::new(BufWriter::new(out), b"\n");
+
+            sink.write(&json!({ "ts": "1" })).unwrap();
+
+            let error = sink.finish().unwrap_err();
+
+            assert!(
+                error.to_string().contains("no space left"),
+                "{:?}: {}",
+                compression,
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{stderr, Write},
+    time::{Duration, Instant},
+};
+
+/// Span of recent pages that the rate is measured over, so that it follows slowdowns (e.g. from
+/// rate limiting) rather than averaging them away
+static RATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Reports the messages and pages fetched so far on a single line of stderr, rewritten as each page
+/// arrives, with an estimate of the time left when the total number of messages is known
+pub struct Progress {
+    out: Box<dyn Write + Send>,
+    expected: Option<u64>,
+    started: Instant,
+    messages: u64,
+    pages: usize,
+    samples: VecDeque<(Instant, u64)>,
+    line_len: usize,
+}
+
+impl Progress {
+    /// Start reporting progress, against an expected total number of messages if there is one
+    pub fn new(expected: Option<u64>) -> Self {
+        let started = Instant::now();
+
+        Self {
+            out: Box::new(stderr()),
+            expected,
+            started,
+            messages: 0,
+            pages: 0,
+            samples: VecDeque::from([(started, 0)]),
+            line_len: 0,
+        }
+    }
+
+    /// Report to another writer than stderr (e.g. a buffer in tests)
+    pub fn with_output(mut self, out: impl Write + Send + 'static) -> Self {
+        self.out = Box::new(out);
+        self
+    }
+
+    /// Count a page of the history as it's fetched, rewriting the progress line
+    pub fn record_page(&mut self, messages: usize) {
+        let now = Instant::now();
+        self.messages += messages as u64;
+        self.pages += 1;
+        self.samples.push_back((now, self.messages));
+
+        // keep the oldest sample within the window as the start of the rate, and drop the rest
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|(fetched, _)| now.duration_since(*fetched) >= RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+
+        let rate = self.samples.front().and_then(|(fetched, messages)| {
+            let elapsed = now.duration_since(*fetched).as_secs_f64();
+
+            (elapsed > 0.0).then(|| (self.messages - messages) as f64 / elapsed)
+        });
+
+        let line = match (rate, self.expected) {
+            (Some(rate), Some(expected)) if rate > 0.0 => {
+                let remaining = expected.saturating_sub(self.messages) as f64 / rate;
+
+                format!(
+                    "{}... {:.0}/s, about {} left",
+                    self.count(),
+                    rate,
+                    format_duration(Duration::from_secs_f64(remaining))
+                )
+            }
+            (Some(rate), _) => format!("{}... {:.0}/s", self.count(), rate),
+            (None, _) => format!("{}...", self.count()),
+        };
+
+        self.rewrite(&line, "");
+    }
+
+    /// End the progress line with a summary once the walk reaches its last page, with the time
+    /// it took and the rate of the whole walk
+    ///
+    /// Nothing is printed unless a page was counted since the last summary, so finishing twice
+    /// (e.g. when a finished walk is polled again) doesn't repeat it.
+    pub fn finish(&mut self) {
+        if self.line_len == 0 {
+            return;
+        }
+
+        let elapsed = self.started.elapsed();
+        let rate = self.messages as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let line = format!(
+            "{} in {} ({:.0}/s)",
+            self.count(),
+            format_duration(elapsed),
+            rate
+        );
+
+        self.rewrite(&line, "\n");
+        self.line_len = 0;
+    }
+
+    /// The messages and pages fetched so far, e.g. `fetched 4000 messages (4 pages)`
+    fn count(&self) -> String {
+        format!("fetched {} messages ({} pages)", self.messages, self.pages)
+    }
+
+    /// Overwrite the current line of the report, padding out anything left of a longer one
+    fn rewrite(&mut self, line: &str, end: &str) {
+        let padding = self.line_len.saturating_sub(line.len());
+        self.line_len = line.len();
+
+        // progress is best-effort, so a closed stderr never fails the export
+        let _ = write!(
+            self.out,
+            "\r{}{:padding$}{}",
+            line,
+            "",
+            end,
+            padding = padding
+        );
+        let _ = self.out.flush();
+    }
+}
+
+// written by hand since the output can't be printed
+impl fmt::Debug for Progress {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Progress")
+            .field("expected", &self.expected)
+            .field("messages", &self.messages)
+            .field("pages", &self.pages)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Format a duration to the second for people to read (e.g. `1h02m05s`)
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", seconds),
+        (0, _) => format!("{}m{:02}s", minutes, seconds),
+        _ => format!("{}h{:02}m{:02}s", hours, minutes, seconds),
+    }
+}
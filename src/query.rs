@@ -0,0 +1,73 @@
+use jaq_core::{
+    data::JustLut,
+    load::{Arena, File, Loader},
+    unwrap_valr, Compiler, Ctx, ValT, Vars,
+};
+use jaq_json::Val;
+use serde::Deserialize;
+use std::fmt;
+
+/// Compiled jq expression that messages must satisfy to be included in an export
+pub struct Query {
+    code: String,
+    filter: jaq_core::Filter<JustLut<Val>>,
+}
+
+impl Query {
+    /// Parse and compile a jq expression, with access to jq's standard library of filters
+    pub fn compile(code: &str) -> anyhow::Result<Self> {
+        let definitions = jaq_core::defs()
+            .chain(jaq_std::defs())
+            .chain(jaq_json::defs());
+        let functions = jaq_core::funs()
+            .chain(jaq_std::funs())
+            .chain(jaq_json::funs());
+
+        let arena = Arena::default();
+        let modules = Loader::new(definitions)
+            .load(&arena, File { code, path: () })
+            .map_err(|errors| {
+                anyhow::anyhow!("Invalid filter expression {}: {:?}", code, errors)
+            })?;
+        let filter = Compiler::default()
+            .with_funs(functions)
+            .compile(modules)
+            .map_err(|errors| {
+                anyhow::anyhow!("Invalid filter expression {}: {:?}", code, errors)
+            })?;
+
+        Ok(Self {
+            code: code.into(),
+            filter,
+        })
+    }
+
+    /// Check whether the first output of the expression for a message is truthy
+    ///
+    /// Expressions that fail to evaluate for a message (e.g. by indexing into a string)
+    /// are treated as not matching that message.
+    pub fn matches(&self, message: &serde_json::Value) -> bool {
+        let input = match Val::deserialize(message) {
+            Ok(input) => input,
+            Err(_) => return false,
+        };
+
+        let context = Ctx::<JustLut<Val>>::new(&self.filter.lut, Vars::new([]));
+        let mut outputs = self.filter.id.run((context, input)).map(unwrap_valr);
+
+        match outputs.next() {
+            Some(Ok(output)) => output.as_bool(),
+            Some(Err(error)) => {
+                tracing::debug!("Filter expression {} failed: {}", self.code, error);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for Query {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_tuple("Query").field(&self.code).finish()
+    }
+}
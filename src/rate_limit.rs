@@ -0,0 +1,97 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Proactive limiter that spaces out requests to stay within a Slack API rate limit tier
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing the provided number of requests per minute
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / requests,
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Block until another request can be made without exceeding the rate limit
+    pub fn wait(&self) {
+        let mut last_request = self
+            .last_request
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(elapsed) = last_request.map(|last_request| last_request.elapsed()) {
+            if elapsed < self.interval {
+                thread::sleep(self.interval - elapsed);
+            }
+        }
+
+        *last_request = Some(Instant::now());
+    }
+}
+
+/// Proactive limiters for each Slack API method, which Slack rate limits independently
+///
+/// Keeping them separate means that a slow tier (e.g. for lookups during enrichment) never holds
+/// back requests to the other methods. Methods without a limiter aren't paced at all.
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    limiters: HashMap<&'static str, RateLimiter>,
+}
+
+impl RateLimits {
+    /// Create limiters for each method (e.g. `conversations.history`) from its requests per minute
+    pub fn new(methods: impl IntoIterator<Item = (&'static str, u32)>) -> Self {
+        let limiters = methods
+            .into_iter()
+            .map(|(method, requests)| (method, RateLimiter::per_minute(requests)))
+            .collect();
+
+        Self { limiters }
+    }
+
+    /// Block until another request can be made to a method without exceeding its rate limit
+    pub fn wait(&self, method: &str) {
+        if let Some(limiter) = self.limiters.get(method) {
+            limiter.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn shared_limits_pace_every_holder() {
+        let rate_limits = Arc::new(RateLimits::new([("conversations.history", 600)]));
+        let other = Arc::clone(&rate_limits);
+        let started = Instant::now();
+
+        rate_limits.wait("conversations.history");
+        other.wait("conversations.history");
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn methods_are_paced_separately() {
+        let rate_limits = RateLimits::new([("conversations.history", 1), ("users.list", 1)]);
+        let started = Instant::now();
+
+        rate_limits.wait("conversations.history");
+        rate_limits.wait("users.list");
+        rate_limits.wait("emoji.list");
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Writes the reactions to exported messages as NDJSON, one record per emoji and user
+///
+/// Each record is an object with the `channel` and `message_ts` of the message alongside the
+/// `emoji` name and the `user` who reacted with it.
+pub struct ReactionWriter {
+    out: BufWriter<File>,
+}
+
+impl ReactionWriter {
+    /// Create (or replace) the file to write reactions to
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .map_err(|error| anyhow::anyhow!("Error creating {}: {}", path.display(), error))?;
+
+        Ok(Self {
+            out: BufWriter::new(file),
+        })
+    }
+
+    /// Write a record for each user's reaction to a message, if it has any
+    pub fn write(&mut self, channel: &str, message: &serde_json::Value) -> anyhow::Result<()> {
+        let message_ts = message.get("ts").and_then(serde_json::Value::as_str);
+        let reactions = message
+            .get("reactions")
+            .and_then(serde_json::Value::as_array);
+
+        let (message_ts, reactions) = match (message_ts, reactions) {
+            (Some(message_ts), Some(reactions)) => (message_ts, reactions),
+            _ => return Ok(()),
+        };
+
+        for reaction in reactions {
+            let emoji = reaction.get("name").and_then(serde_json::Value::as_str);
+            let users = reaction.get("users").and_then(serde_json::Value::as_array);
+
+            let (emoji, users) = match (emoji, users) {
+                (Some(emoji), Some(users)) => (emoji, users),
+                _ => continue,
+            };
+
+            for user in users {
+                let record = serde_json::json!({
+                    "channel": channel,
+                    "message_ts": message_ts,
+                    "emoji": emoji,
+                    "user": user,
+                });
+
+                serde_json::to_writer(&mut self.out, &record)?;
+                self.out.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flush every record to the file once the export is complete
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+
+        Ok(())
+    }
+}
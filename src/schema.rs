@@ -0,0 +1,106 @@
+use super::Configuration;
+use serde_json::{json, Map, Value};
+use std::{fs::File, path::Path};
+
+/// Dialect of JSON Schema used for the message schema
+static SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Describe the shape of each exported message as a JSON Schema, reflecting the configured
+/// annotations, exclusions and thread handling
+///
+/// Slack adds fields over time, so fields that aren't described are still allowed.
+pub fn message_schema(configuration: &Configuration) -> Value {
+    let mut properties = Map::new();
+
+    properties.insert("type".into(), json!({ "type": "string" }));
+    properties.insert("subtype".into(), json!({ "type": "string" }));
+    properties.insert(
+        "ts".into(),
+        json!({ "type": "string", "pattern": "^[0-9]+\\.[0-9]+$" }),
+    );
+    properties.insert("user".into(), json!({ "type": "string" }));
+    properties.insert("bot_id".into(), json!({ "type": "string" }));
+    properties.insert("text".into(), json!({ "type": "string" }));
+    properties.insert(
+        "thread_ts".into(),
+        json!({ "type": "string", "pattern": "^[0-9]+\\.[0-9]+$" }),
+    );
+    properties.insert(
+        "reply_count".into(),
+        json!({ "type": "integer", "minimum": 0 }),
+    );
+    properties.insert("reactions".into(), json!({ "type": "array" }));
+
+    if configuration.add_permalinks || configuration.local_permalinks {
+        properties.insert(
+            "permalink".into(),
+            json!({ "type": "string", "format": "uri" }),
+        );
+    }
+
+    if configuration.add_epoch_ms {
+        properties.insert("ts_epoch".into(), json!({ "type": "integer" }));
+    }
+
+    if configuration.add_iso_ts {
+        properties.insert(
+            "ts_iso".into(),
+            json!({ "type": "string", "format": "date-time" }),
+        );
+    }
+
+    if configuration.resolve_names {
+        properties.insert("_slump_user_name".into(), json!({ "type": "string" }));
+    }
+
+    if configuration.collapse_consecutive.is_some() {
+        properties.insert(
+            "ts_list".into(),
+            json!({ "type": "array", "items": { "type": "string" }, "minItems": 2 }),
+        );
+    }
+
+    if configuration.include_replies() && configuration.max_thread_requests.is_some() {
+        properties.insert("replies_truncated".into(), json!({ "type": "boolean" }));
+    }
+
+    // nested replies are messages themselves
+    if configuration.include_replies() && configuration.nest_threads {
+        properties.insert(
+            "thread".into(),
+            json!({ "type": "array", "items": { "$ref": "#" } }),
+        );
+    }
+
+    // excluded fields never appear, which a `false` schema enforces
+    for field in &configuration.exclude_fields {
+        properties.insert(field.clone(), Value::Bool(false));
+    }
+
+    let required: Vec<_> = ["ts"]
+        .into_iter()
+        .filter(|field| {
+            !configuration
+                .exclude_fields
+                .iter()
+                .any(|excluded| excluded == field)
+        })
+        .collect();
+
+    json!({
+        "$schema": SCHEMA_DIALECT,
+        "title": "Slack message",
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": true,
+    })
+}
+
+/// Write the schema of each exported message to a file
+pub fn write_schema(path: &Path, configuration: &Configuration) -> anyhow::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, &message_schema(configuration))?;
+
+    Ok(())
+}
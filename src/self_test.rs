@@ -0,0 +1,147 @@
+use super::export;
+use chrono_tz::Tz;
+use slump::{Configuration, OnExisting, Slack, Stats};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+/// Channel served by the mock Slack API
+static CHANNEL: &str = "C0SELFTEST";
+
+/// Cursor to the second (and last) page of the mock channel's history
+static SECOND_PAGE_CURSOR: &str = "page-2";
+
+/// `ts` of every message in the mock channel, newest first as Slack returns them
+static MESSAGE_TS: [&str; 3] = [
+    "1700000003.000300",
+    "1700000002.000200",
+    "1700000001.000100",
+];
+
+/// Export a canned two-page channel from a mock Slack API on a local port, checking the output
+///
+/// This needs neither credentials nor network access, so it shows whether the binary itself works.
+pub fn run() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+
+    // the server thread is never joined, and ends with the process
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(error) = respond(stream) {
+                tracing::warn!("Mock Slack API failed to respond: {}", error);
+            }
+        }
+    });
+
+    // the export is deleted along with its guard, however the self-test ends
+    let export_file = tempfile::Builder::new()
+        .prefix("slump-self-test-")
+        .suffix(".json")
+        .tempfile()?;
+    let path = export_file.path().to_owned();
+    let configuration = Configuration {
+        on_existing: OnExisting::Overwrite,
+        ..Configuration::new("xoxb-self-test", CHANNEL)
+    };
+    let slack = Slack::try_from(configuration.clone())?.with_base_url(base_url);
+    let mut stats = Stats::new(Tz::UTC);
+
+    let exported = export(
+        &configuration,
+        &slack,
+        Some(path.clone()),
+        None,
+        None,
+        None,
+        &mut stats,
+    )
+    .and_then(|_| Ok(fs::read_to_string(&path)?));
+    drop(export_file);
+
+    let messages: Vec<serde_json::Value> = serde_json::from_str(&exported?)?;
+    let ts: Vec<_> = messages
+        .iter()
+        .map(|message| message.get("ts").and_then(serde_json::Value::as_str))
+        .collect();
+
+    if ts != MESSAGE_TS.map(Some) {
+        anyhow::bail!(
+            "Self-test failed: expected messages {:?}, but the export had {:?}",
+            MESSAGE_TS,
+            ts
+        );
+    }
+
+    if slack.request_count() != 2 {
+        anyhow::bail!(
+            "Self-test failed: expected 2 requests for 2 pages, but made {}",
+            slack.request_count()
+        );
+    }
+
+    eprintln!(
+        "Self-test passed: exported {} messages across 2 pages",
+        messages.len()
+    );
+
+    Ok(())
+}
+
+/// Answer a single request to the mock Slack API, closing the connection afterwards
+fn respond(mut stream: TcpStream) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // skip the headers, since there's no request body to find
+    let mut header = String::new();
+
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (endpoint, query) = target.split_once('?').unwrap_or((target, ""));
+    let is_second_page = query
+        .split('&')
+        .any(|parameter| parameter == format!("cursor={}", SECOND_PAGE_CURSOR));
+
+    let body = match endpoint {
+        "/conversations.history" if is_second_page => serde_json::json!({
+            "ok": true,
+            "messages": [message(MESSAGE_TS[2])],
+            "has_more": false,
+        }),
+        "/conversations.history" => serde_json::json!({
+            "ok": true,
+            "messages": [message(MESSAGE_TS[0]), message(MESSAGE_TS[1])],
+            "has_more": true,
+            "response_metadata": { "next_cursor": SECOND_PAGE_CURSOR },
+        }),
+        _ => serde_json::json!({ "ok": false, "error": "unknown_method" }),
+    }
+    .to_string();
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+
+    Ok(stream.flush()?)
+}
+
+/// A canned message from the mock channel
+fn message(ts: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "message",
+        "user": "U0SELFTEST",
+        "text": format!("Self-test message {}", ts),
+        "ts": ts,
+    })
+}
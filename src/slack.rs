@@ -1,89 +1,2099 @@
-use super::Configuration;
+use super::{dates, rate_limit::RateLimits, redact, Checkpoint, Configuration, Error, Progress};
+use chrono_tz::Tz;
+#[cfg(feature = "fallible-iterator")]
 use fallible_iterator::FallibleIterator;
-use reqwest::blocking::Client;
-use serde::Deserialize;
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response as HttpResponse},
+    header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    redirect::Policy,
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Lines, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+mod stream;
+
+#[cfg(feature = "async")]
+pub use stream::AsyncSlack;
+
+#[cfg(test)]
+mod tests;
+
+/// Error codes for a token that Slack won't accept however often the request is retried
+static AUTH_ERRORS: [&str; 3] = ["account_inactive", "no_permission", "token_revoked"];
+
+/// Non-configurable static values for the Slack API
+static DEFAULT_BASE_URL: &str = "https://slack.com/api";
+static API_TEST_ENDPOINT: &str = "api.test";
+static CHAT_PERMALINK_ENDPOINT: &str = "chat.getPermalink";
+static CONVERSATION_HISTORY_ENDPOINT: &str = "conversations.history";
+static CONVERSATION_INFO_ENDPOINT: &str = "conversations.info";
+static CONVERSATION_LIST_ENDPOINT: &str = "conversations.list";
+static CONVERSATION_MEMBERS_ENDPOINT: &str = "conversations.members";
+static CONVERSATION_REPLIES_ENDPOINT: &str = "conversations.replies";
+static EMOJI_LIST_ENDPOINT: &str = "emoji.list";
+static SEARCH_MESSAGES_ENDPOINT: &str = "search.messages";
+static USER_LIST_ENDPOINT: &str = "users.list";
+static RESPONSE_MESSAGE_LIMIT: i16 = 1000;
+static SEARCH_RESULT_LIMIT: u32 = 100;
+static ERROR_BODY_SNIPPET_LENGTH: usize = 200;
+
+/// Largest response body checked for a `missing_scope` error, well above any error response but
+/// far below a page of messages
+static MAX_ERROR_BODY_LENGTH: usize = 4096;
+
+/// Response headers listing the scopes of the token, and the scopes that the method accepts
+static OAUTH_SCOPES_HEADER: &str = "x-oauth-scopes";
+static ACCEPTED_OAUTH_SCOPES_HEADER: &str = "x-accepted-oauth-scopes";
+
+/// Redirects followed within the same host before a request is abandoned
+static MAX_REDIRECTS: usize = 5;
+
+/// Retries of requests that fail with a network error or a server error, doubling the delay each
+/// time
+static MAX_RETRIES: u32 = 3;
+static INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Retries of rate limited requests, each after waiting as long as Slack asked, counted apart from
+/// other retries so that a throttled token eventually fails instead of waiting forever
+static MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Prefixes of the response headers kept with `CAPTURE_HEADERS`, which cover rate limiting and
+/// Slack's request tracing (e.g. `x-slack-req-id`) without ever including credentials
+static CAPTURED_HEADER_PREFIXES: [&str; 2] = ["x-ratelimit-", "x-slack-"];
+
+/// Requests per minute allowed by the Slack API's rate limit tiers, taking the documented minimum
+/// for each tier (Slack allows occasional bursts above these)
+static TIER_2_REQUESTS_PER_MINUTE: u32 = 20;
+static TIER_3_REQUESTS_PER_MINUTE: u32 = 50;
+static TIER_4_REQUESTS_PER_MINUTE: u32 = 100;
+
+/// Top-level Slack API client for a single channel
+pub struct Slack {
+    api_token: String,
+    auth_header: Option<(HeaderName, HeaderValue)>,
+    channel: String,
+    client: Client,
+    base_url: String,
+    bounds: Bounds,
+    include_all_metadata: bool,
+    include_replies: bool,
+    max_thread_requests: Option<usize>,
+    page_cap: Option<usize>,
+    history_limit: i16,
+    replies_limit: i16,
+    start_cursor: Option<String>,
+    timezone: Tz,
+    walk_by_day: bool,
+    direction: Direction,
+    recover_invalid_cursor: bool,
+    latest_only: bool,
+    nest_threads: bool,
+    check_ordering: bool,
+    requests: AtomicU64,
+    retries: AtomicU64,
+    rate_limits: Arc<RateLimits>,
+    raw_dump: Option<Mutex<LineWriter<File>>>,
+    replay: Option<Mutex<Lines<BufReader<File>>>>,
+    history_latencies: Mutex<Vec<Duration>>,
+    capture_headers: bool,
+    last_headers: Mutex<BTreeMap<String, String>>,
+    retry_if: Option<Box<RetryPredicate>>,
+    checkpoint: Option<Checkpoint>,
+    deadline: Option<Instant>,
+    reached_deadline: AtomicBool,
+    user_names: Option<Arc<HashMap<String, String>>>,
+    progress: Option<Arc<Mutex<Progress>>>,
+}
+
+/// Order in which a walk through the history yields messages
+///
+/// Slack's cursors only ever page from the newest message to the oldest (each page is anchored at
+/// the `latest` bound, or at the previous page), so walking oldest first reverses the order on this
+/// side instead. That's only possible a bounded window at a time, which is why it requires walking
+/// by day: the days are walked from `oldest` onwards, and the pages of each day are fetched in full
+/// before being yielded in reverse.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Newest message first, as Slack returns them
+    #[default]
+    NewestFirst,
+    /// Oldest message first, in the order they were sent
+    OldestFirst,
+}
+
+/// Kind of conversation a channel ID is likely to refer to, judging by its prefix alone
+///
+/// Slack doesn't guarantee what prefixes mean: channels made private keep their `C` prefix, and
+/// group DMs created before 2021 share the `G` prefix of older private channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConversationType {
+    /// A public channel (`C`)
+    PublicChannel,
+    /// A private channel (`G`)
+    PrivateChannel,
+    /// A direct message between two users (`D`)
+    DirectMessage,
+}
+
+impl ConversationType {
+    /// Scope a token needs to read the history of this kind of conversation
+    pub fn history_scope(self) -> &'static str {
+        match self {
+            Self::PublicChannel => "channels:history",
+            Self::PrivateChannel => "groups:history",
+            Self::DirectMessage => "im:history",
+        }
+    }
+}
+
+impl fmt::Display for ConversationType {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PublicChannel => write!(formatter, "public channel"),
+            Self::PrivateChannel => write!(formatter, "private channel"),
+            Self::DirectMessage => write!(formatter, "direct message"),
+        }
+    }
+}
+
+/// Decides whether a failed request should be retried, given the error it failed with
+pub type RetryPredicate = dyn Fn(&anyhow::Error) -> bool + Send + Sync;
+
+impl Slack {
+    /// Create the HTTP client used unless one is provided, which refuses to follow redirects to
+    /// another host
+    ///
+    /// Redirects to another host drop the `Authorization` header, so following them would only
+    /// fail later with a confusing `not_authed` or `invalid_auth` error. The connection pool keeps
+    /// reqwest's defaults unless the configuration sets its idle timeout or size.
+    pub fn default_client(configuration: &Configuration) -> anyhow::Result<Client> {
+        let mut builder = Client::builder().redirect(redirect_policy());
+
+        if let Some(secs) = configuration.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+        }
+
+        if let Some(max_idle) = configuration.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Create the proactive limiters used unless shared ones are provided, pacing each method at
+    /// the documented minimum of its tier
+    pub fn default_rate_limits() -> RateLimits {
+        RateLimits::new([
+            (CHAT_PERMALINK_ENDPOINT, TIER_4_REQUESTS_PER_MINUTE),
+            (CONVERSATION_HISTORY_ENDPOINT, TIER_3_REQUESTS_PER_MINUTE),
+            (CONVERSATION_INFO_ENDPOINT, TIER_3_REQUESTS_PER_MINUTE),
+            (CONVERSATION_LIST_ENDPOINT, TIER_2_REQUESTS_PER_MINUTE),
+            (CONVERSATION_MEMBERS_ENDPOINT, TIER_4_REQUESTS_PER_MINUTE),
+            (CONVERSATION_REPLIES_ENDPOINT, TIER_3_REQUESTS_PER_MINUTE),
+            (EMOJI_LIST_ENDPOINT, TIER_2_REQUESTS_PER_MINUTE),
+            (SEARCH_MESSAGES_ENDPOINT, TIER_2_REQUESTS_PER_MINUTE),
+            (USER_LIST_ENDPOINT, TIER_2_REQUESTS_PER_MINUTE),
+        ])
+    }
+
+    /// Create a Slack API client that shares a pre-configured HTTP client (and its connection pool)
+    pub fn with_client(configuration: Configuration, client: Client) -> anyhow::Result<Self> {
+        let timezone = configuration.timezone()?;
+        let history_limit = configuration.history_limit();
+        let replies_limit = configuration.replies_limit();
+        let include_replies = configuration.include_replies();
+        let Configuration {
+            api_token,
+            auth_header,
+            slack_base_url,
+            channel,
+            from_date,
+            to_date,
+            oldest,
+            latest,
+            include_all_metadata,
+            max_thread_requests,
+            page_cap,
+            start_cursor,
+            walk_by_day,
+            direction,
+            recover_invalid_cursor,
+            latest_only,
+            nest_threads,
+            list_channels,
+            check_ordering,
+            dump_raw,
+            replay_from,
+            capture_headers,
+            oldest_first_stream,
+            ..
+        } = configuration;
+
+        // streaming oldest first is a walk by day in that direction
+        let walk_by_day = walk_by_day || oldest_first_stream;
+        let direction = if oldest_first_stream {
+            Direction::OldestFirst
+        } else {
+            direction
+        };
+
+        let bounds = Bounds::resolve(from_date, to_date, oldest, latest, timezone)?;
+
+        // listing conversations doesn't need a channel of its own
+        let channel = if list_channels && channel.trim().is_empty() {
+            String::new()
+        } else {
+            normalize_channel(&channel)?
+        };
+        let auth_header = auth_header.as_deref().map(parse_header).transpose()?;
+        let raw_dump = dump_raw
+            .map(|path| {
+                File::create(&path)
+                    .map(|file| Mutex::new(LineWriter::new(file)))
+                    .map_err(|error| {
+                        anyhow::anyhow!("Error creating {}: {}", path.display(), error)
+                    })
+            })
+            .transpose()?;
+        let replay = replay_from
+            .map(|path| {
+                File::open(&path)
+                    .map(|file| Mutex::new(BufReader::new(file).lines()))
+                    .map_err(|error| anyhow::anyhow!("Error opening {}: {}", path.display(), error))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            api_token,
+            auth_header,
+            channel,
+            client,
+            base_url: base_url(slack_base_url),
+            bounds,
+            include_all_metadata,
+            include_replies,
+            max_thread_requests,
+            page_cap,
+            history_limit,
+            replies_limit,
+            start_cursor,
+            timezone,
+            walk_by_day,
+            direction,
+            recover_invalid_cursor,
+            latest_only,
+            nest_threads,
+            check_ordering,
+            requests: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            rate_limits: Arc::new(Self::default_rate_limits()),
+            raw_dump,
+            replay,
+            history_latencies: Mutex::new(Vec::new()),
+            capture_headers,
+            last_headers: Mutex::new(BTreeMap::new()),
+            retry_if: None,
+            checkpoint: None,
+            deadline: None,
+            reached_deadline: AtomicBool::new(false),
+            user_names: None,
+            progress: None,
+        })
+    }
+
+    /// Send every request to another root URL (e.g. a mock server) instead of the configured one
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into().trim_end_matches('/').into();
+        self
+    }
+
+    /// Pace requests with limiters shared with other clients (e.g. one per channel of an export),
+    /// so that Slack's per-method limits hold across all of them rather than for each client alone
+    pub fn with_rate_limits(mut self, rate_limits: Arc<RateLimits>) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Resume the walk through the history from a checkpoint left by an earlier, interrupted walk
+    ///
+    /// If Slack rejects the checkpoint's cursor (e.g. because it expired), the walk restarts from
+    /// the newest message older than the checkpoint's last `ts` instead.
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Report each page of the history on a progress line as it's fetched, sharing the count with
+    /// other clients (e.g. one per channel of an export)
+    pub fn with_progress(mut self, progress: Arc<Mutex<Progress>>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Stop every walk through the history at the next chunk boundary once a deadline passes
+    ///
+    /// Messages from chunks that were already fetched are still yielded before the walk ends.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Resolve user IDs with names listed earlier (e.g. by [`Slack::user_names`] for another
+    /// channel), so that every channel of a run shares a single listing
+    pub fn with_user_names(mut self, user_names: Arc<HashMap<String, String>>) -> Self {
+        self.user_names = Some(user_names);
+        self
+    }
+
+    /// Look up the name of a user from the names provided with [`Slack::with_user_names`]
+    pub fn user_name(&self, user: &str) -> Option<&str> {
+        self.user_names.as_ref()?.get(user).map(String::as_str)
+    }
+
+    /// The checkpoint the walk through the history resumes from, if any
+    pub fn checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Check whether a walk through the history was stopped because the deadline passed
+    pub fn reached_deadline(&self) -> bool {
+        self.reached_deadline.load(Ordering::Relaxed)
+    }
+
+    /// Check whether the deadline has passed, remembering it for `reached_deadline` if so
+    fn is_past_deadline(&self) -> bool {
+        let is_past_deadline = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+
+        if is_past_deadline {
+            self.reached_deadline.store(true, Ordering::Relaxed);
+        }
+
+        is_past_deadline
+    }
+
+    /// Decide which failed requests are retried with a custom predicate, instead of
+    /// [`Slack::is_retryable`]
+    ///
+    /// The predicate sees each failed attempt's error, which can be downcast to a
+    /// [`crate::Error`] (e.g. `Server` or `RateLimited`) or a `reqwest::Error` for network errors.
+    /// Retries still back off and stop after the usual number of attempts.
+    pub fn with_retry_if(
+        mut self,
+        retry_if: impl Fn(&anyhow::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_if = Some(Box::new(retry_if));
+        self
+    }
+
+    /// Check whether a failed request is worth retrying by default: network errors (failed
+    /// connections, timeouts and responses cut off partway through), server errors and rate
+    /// limiting are, anything else (including complete but malformed responses) isn't
+    pub fn is_retryable(error: &anyhow::Error) -> bool {
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            return error.is_connect() || error.is_timeout() || error.is_body();
+        }
+
+        matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Server { .. } | Error::RateLimited { .. })
+        )
+    }
+
+    /// Only fetch messages sent after a Slack timestamp (exclusive), if it's newer than the
+    /// configured oldest date
+    pub fn with_oldest(mut self, oldest: &str) -> Self {
+        let is_newer = match &self.bounds.oldest {
+            Some(current) => {
+                dates::from_slack_timestamp(oldest) > dates::from_slack_timestamp(current)
+            }
+            None => true,
+        };
+
+        if is_newer {
+            self.bounds.oldest = Some(oldest.into());
+        }
+
+        self
+    }
+
+    /// Fetch a single chunk of messages from the conversation history API
+    fn get_message_chunk(
+        &self,
+        bounds: &Bounds,
+        cursor: Option<&String>,
+    ) -> anyhow::Result<MessageChunk> {
+        self.get_history_chunk(bounds, cursor, self.history_limit)
+    }
+
+    /// Fetch the first chunk of a day's messages, or every message of the day in one chunk (oldest
+    /// first) when walking oldest first
+    fn get_day_chunk(&self, bounds: &Bounds) -> anyhow::Result<MessageChunk> {
+        if self.direction == Direction::NewestFirst {
+            return self.get_message_chunk(bounds, None);
+        }
+
+        let mut messages = Vec::new();
+        let mut chunk = self.get_message_chunk(bounds, None)?;
+
+        loop {
+            let next_cursor = match &chunk {
+                MessageChunk::NonTerminal { next_cursor, .. } => Some(next_cursor.clone()),
+                MessageChunk::Terminal { .. } => None,
+            };
+
+            messages.extend(chunk);
+
+            match next_cursor {
+                Some(next_cursor) => chunk = self.get_message_chunk(bounds, Some(&next_cursor))?,
+                None => break,
+            }
+        }
+
+        messages.reverse();
+
+        Ok(MessageChunk::Terminal {
+            messages: messages.into_iter(),
+        })
+    }
+
+    /// Fetch a single chunk of up to `limit` messages from the conversation history API
+    fn get_history_chunk(
+        &self,
+        bounds: &Bounds,
+        cursor: Option<&String>,
+        limit: i16,
+    ) -> anyhow::Result<MessageChunk> {
+        let mut request = self
+            .get(CONVERSATION_HISTORY_ENDPOINT)
+            .query(&[("channel", &self.channel), ("limit", &limit.to_string())]);
+
+        // cursors are opaque and may contain reserved characters, so leave encoding to reqwest
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+
+        if let Some(oldest) = &bounds.oldest {
+            request = request.query(&[("oldest", oldest)]);
+        }
+
+        if let Some(latest) = &bounds.latest {
+            request = request.query(&[("latest", latest)]);
+        }
+
+        if self.include_all_metadata {
+            request = request.query(&[("include_all_metadata", "true")]);
+        }
+
+        // never log the token itself, which is sent as a header rather than a query parameter
+        tracing::debug!(
+            channel = %self.channel,
+            limit,
+            has_cursor = cursor.is_some(),
+            oldest = ?bounds.oldest,
+            latest = ?bounds.latest,
+            include_all_metadata = self.include_all_metadata,
+            "Fetching conversation history"
+        );
+
+        let started = Instant::now();
+        let chunk = self.send(request);
+        let elapsed = started.elapsed();
+
+        tracing::debug!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Fetched conversation history"
+        );
+
+        if let Ok(mut history_latencies) = self.history_latencies.lock() {
+            history_latencies.push(elapsed);
+        }
+
+        chunk
+    }
+
+    /// Fetch a single chunk of replies to a thread from the conversation replies API
+    fn get_reply_chunk(
+        &self,
+        thread_ts: &str,
+        cursor: Option<&String>,
+    ) -> anyhow::Result<MessageChunk> {
+        let mut request = self.get(CONVERSATION_REPLIES_ENDPOINT).query(&[
+            ("channel", self.channel.as_str()),
+            ("ts", thread_ts),
+            ("limit", &self.replies_limit.to_string()),
+        ]);
+
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+
+        tracing::debug!(
+            channel = %self.channel,
+            thread_ts,
+            limit = self.replies_limit,
+            has_cursor = cursor.is_some(),
+            "Fetching thread replies"
+        );
+
+        self.send(request)
+    }
+
+    /// Build an authenticated GET request for a Slack API endpoint, once its rate limit allows
+    fn get(&self, endpoint: &str) -> RequestBuilder {
+        // replayed requests are never sent, so they needn't wait for the rate limit either
+        if self.replay.is_none() {
+            self.rate_limits.wait(endpoint);
+        }
+
+        let request = self.client.get(format!("{}/{}", self.base_url, endpoint));
+
+        match &self.auth_header {
+            Some((name, value)) => request.header(name, value),
+            None => request.bearer_auth(&self.api_token),
+        }
+    }
+
+    /// Send a request to the Slack API, processing the response into a chunk of messages
+    ///
+    /// When replaying, the request is never sent, and the next saved response is used instead.
+    fn send(&self, request: RequestBuilder) -> anyhow::Result<MessageChunk> {
+        let raw: serde_json::Value = match &self.replay {
+            Some(replay) => Self::next_replayed(replay)?,
+            None => self.fetch(request)?,
+        };
+
+        // keep the whole envelope (not just the messages) for auditing and debugging pagination
+        if let Some(raw_dump) = &self.raw_dump {
+            let mut raw_dump = raw_dump
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Raw response dump is unavailable"))?;
+            serde_json::to_writer(&mut *raw_dump, &raw)?;
+            raw_dump.write_all(b"\n")?;
+        }
+
+        serde_json::from_value::<Response>(raw)?.try_into()
+    }
+
+    /// Read the next response saved by `DUMP_RAW`, in the order the requests were made
+    fn next_replayed(replay: &Mutex<Lines<BufReader<File>>>) -> anyhow::Result<serde_json::Value> {
+        let mut replay = replay
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Replayed responses are unavailable"))?;
+
+        // blank lines can't be responses, but are easily left behind by editing a dump by hand
+        let line = loop {
+            match replay.next().transpose()? {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => break line,
+                None => anyhow::bail!(
+                    "REPLAY_FROM ran out of responses: replay with the same options that decide which requests are made (e.g. INCLUDE_REPLIES) as the export that saved them"
+                ),
+            }
+        };
+
+        serde_json::from_str(&line)
+            .map_err(|error| anyhow::anyhow!("Error parsing a replayed response: {}", error))
+    }
+
+    /// Send a request to the Slack API, parsing the response body as JSON
+    fn fetch<T: DeserializeOwned>(&self, request: RequestBuilder) -> anyhow::Result<T> {
+        // a replay never reaches the network, and only has history and replies to offer
+        if self.replay.is_some() {
+            anyhow::bail!(
+                "REPLAY_FROM only has conversation history and replies, so this export needs requests that can't be replayed"
+            );
+        }
+
+        let (body, scopes) = self.send_with_retries(request)?;
+
+        // error responses are small, so pages of messages are never parsed twice to find one
+        if body.len() <= MAX_ERROR_BODY_LENGTH {
+            if let Ok(envelope) = serde_json::from_slice::<ErrorEnvelope>(&body) {
+                if !envelope.ok && envelope.error.as_deref() == Some("missing_scope") {
+                    return Err(Error::MissingScope {
+                        needed: envelope.needed,
+                        provided: scopes.provided.or(envelope.provided),
+                        accepted: scopes.accepted,
+                    }
+                    .into());
+                }
+            }
+        }
+
+        parse_body(&body)
+    }
+
+    /// Send a request and read its body, retrying transient network errors and server errors (5xx)
+    /// with backoff, and rate limited requests (429) once Slack's `Retry-After` has passed
+    ///
+    /// A connection that drops partway through the body is a network error too, and retrying it
+    /// fetches the same page again, since the cursor only advances once a body has been parsed.
+    /// Only a complete body is ever returned, so nothing from a failed attempt reaches the output.
+    /// Other client errors (4xx) are returned without retrying, unless a custom retry predicate
+    /// says otherwise.
+    ///
+    /// The response's OAuth scope headers are returned along with its body, since they're only
+    /// available until the body has been read.
+    fn send_with_retries(&self, request: RequestBuilder) -> anyhow::Result<(Vec<u8>, OauthScopes)> {
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 0;
+        let mut retries = 0;
+        let mut rate_limit_retries = 0;
+
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| anyhow::anyhow!("Request to the Slack API can't be retried"))?;
+
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            attempt += 1;
+
+            let response = attempt_request
+                .send()
+                .inspect(|response| self.capture_headers_of(response));
+
+            let error: anyhow::Error = match response {
+                Ok(response) if response.status().is_server_error() => Error::Server {
+                    status: response.status(),
+                }
+                .into(),
+                // surface rate limiting as a typed error so that callers can reschedule
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    Error::RateLimited {
+                        retry_after: retry_after(response.headers()),
+                    }
+                    .into()
+                }
+                Ok(response) => {
+                    let scopes = OauthScopes::from_headers(&response);
+
+                    match response.bytes() {
+                        Ok(body) => return Ok((body.to_vec(), scopes)),
+                        Err(error) => anyhow::Error::from(error)
+                            .context("Connection dropped while reading the Slack API response"),
+                    }
+                }
+                Err(error) => error.into(),
+            };
+
+            let is_retryable = match &self.retry_if {
+                Some(retry_if) => retry_if(&error),
+                None => Self::is_retryable(&error),
+            };
+
+            if !is_retryable {
+                return Err(error);
+            }
+
+            let rate_limited = match error.downcast_ref::<Error>() {
+                Some(Error::RateLimited { retry_after }) => Some(*retry_after),
+                _ => None,
+            };
+
+            // rate limiting has its own budget, since waiting it out is expected on big exports
+            if rate_limited.is_some() {
+                if rate_limit_retries == MAX_RATE_LIMIT_RETRIES {
+                    return Err(error.context(format!(
+                        "Still rate limited by the Slack API after {} attempts: wait a while before trying again",
+                        attempt
+                    )));
+                }
+
+                rate_limit_retries += 1;
+            } else if retries == MAX_RETRIES {
+                // failing to resolve the host points at the local network rather than at Slack
+                if is_dns_error(&error) {
+                    return Err(error.context(format!(
+                        "Couldn't resolve the host of {} after {} attempts: check the network connection and DNS settings",
+                        self.base_url, attempt
+                    )));
+                }
+
+                return Err(error.context(format!("Giving up after {} attempts", attempt)));
+            } else {
+                retries += 1;
+            }
+
+            // wait exactly as long as a rate limited response asked, backing off otherwise
+            let wait = match rate_limited.flatten() {
+                Some(retry_after) => retry_after,
+                None => {
+                    let wait = delay;
+                    delay *= 2;
+                    wait
+                }
+            };
+
+            tracing::warn!("{}, retrying in {:?}", error, wait);
+            self.retries.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(wait);
+        }
+    }
+
+    /// Keep the captured headers of a response (including errors like rate limiting), replacing
+    /// those of the previous response
+    fn capture_headers_of(&self, response: &HttpResponse) {
+        if !self.capture_headers {
+            return;
+        }
+
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| is_captured_header(name) || *name == RETRY_AFTER)
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect();
+
+        if let Ok(mut last_headers) = self.last_headers.lock() {
+            *last_headers = headers;
+        }
+    }
+
+    /// Check whether the channel has any messages within the configured dates, with a single
+    /// minimal request
+    pub fn has_messages(&self) -> anyhow::Result<bool> {
+        let chunk = self.get_history_chunk(&self.bounds, None, 1)?;
+
+        Ok(chunk.has_remaining())
+    }
+
+    /// Classify a channel ID by its prefix, without making any requests
+    ///
+    /// This is only a guess (see [`ConversationType`]), and IDs with an unknown prefix aren't
+    /// classified at all.
+    pub fn conversation_type(channel: &str) -> Option<ConversationType> {
+        match channel.chars().next()? {
+            'C' => Some(ConversationType::PublicChannel),
+            'G' => Some(ConversationType::PrivateChannel),
+            'D' => Some(ConversationType::DirectMessage),
+            _ => None,
+        }
+    }
+
+    /// Check that the channel exists and its history is readable, with a single minimal request
+    pub fn validate_channel(&self) -> anyhow::Result<()> {
+        let error = match self.get_history_chunk(&Bounds::default(), None, 1) {
+            Ok(_) => return Ok(()),
+            Err(error) => error,
+        };
+
+        let conversation_type = Self::conversation_type(&self.channel);
+
+        // keep the Slack error as the cause, so that its code can still be reported
+        let hint = match (error.downcast_ref::<Error>(), conversation_type) {
+            (Some(Error::Api { error }), Some(conversation_type))
+                if error == "channel_not_found" =>
+            {
+                format!(
+                    "Channel {} doesn't exist or isn't visible to this token: if it's a {}, the token needs the {} scope",
+                    self.channel,
+                    conversation_type,
+                    conversation_type.history_scope()
+                )
+            }
+            (Some(Error::Api { error }), None) if error == "channel_not_found" => format!(
+                "Channel {} doesn't exist or isn't visible to this token",
+                self.channel
+            ),
+            (Some(Error::Api { error }), _) if error == "not_in_channel" => format!(
+                "Not a member of channel {}: add the app to the channel first",
+                self.channel
+            ),
+            // Slack doesn't always say which scope was missing
+            (Some(Error::MissingScope { needed: None, .. }), Some(conversation_type)) => format!(
+                "Channel {} looks like a {}, whose history needs the {} scope",
+                self.channel,
+                conversation_type,
+                conversation_type.history_scope()
+            ),
+            _ => return Err(error),
+        };
+
+        Err(error.context(hint))
+    }
+
+    /// Fetch the channel's metadata (name, topic, purpose, creation details and so on)
+    pub fn info(&self) -> anyhow::Result<serde_json::Value> {
+        let request = self
+            .get(CONVERSATION_INFO_ENDPOINT)
+            .query(&[("channel", &self.channel)]);
+        let response: ChannelInfoResponse = self.fetch(request)?;
+
+        match response.channel {
+            Some(channel) if response.ok => Ok(channel),
+            _ => Err(api_error(response.error, response.needed)),
+        }
+    }
+
+    /// Look up when the channel was created, as a Slack timestamp
+    fn created_ts(&self) -> anyhow::Result<String> {
+        let created = self
+            .info()?
+            .get("created")
+            .and_then(serde_json::Value::as_i64)
+            .ok_or_else(|| anyhow::anyhow!("Channel {} has no creation time", self.channel))?;
+
+        Ok(format!("{}.000000", created))
+    }
+
+    /// Fetch the workspace's custom emoji, mapping each name to its image URL
+    ///
+    /// Aliases map to `alias:` followed by the name of the emoji they stand for.
+    pub fn emoji(&self) -> anyhow::Result<HashMap<String, String>> {
+        tracing::debug!("Listing custom emoji");
+
+        let response: EmojiListResponse = self.fetch(self.get(EMOJI_LIST_ENDPOINT))?;
+
+        match response.emoji {
+            Some(emoji) if response.ok => Ok(emoji),
+            _ => Err(api_error(response.error, response.needed)),
+        }
+    }
+
+    /// Check that the Slack API can be reached, returning the round trip time of a single request
+    ///
+    /// This calls `api.test`, which needs no authentication, so it's sent without any credentials
+    /// and works whether or not they're valid. It isn't retried, since a probe should report the
+    /// first failure.
+    pub fn ping(&self) -> anyhow::Result<Duration> {
+        let request = self
+            .client
+            .get(format!("{}/{}", self.base_url, API_TEST_ENDPOINT));
+
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let started = Instant::now();
+        let body = request.send()?.error_for_status()?.bytes()?;
+        let elapsed = started.elapsed();
+
+        let response: ApiTestResponse = serde_json::from_slice(&body)
+            .map_err(|error| anyhow::anyhow!("Error parsing Slack API response: {}", error))?;
+
+        if !response.ok {
+            return Err(api_error(response.error, None));
+        }
+
+        tracing::debug!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Pinged the Slack API"
+        );
+
+        Ok(elapsed)
+    }
+
+    /// Fetch the permanent link to a message in the channel
+    ///
+    /// Each call makes a request to the Slack API, paced to stay within the method's rate limit.
+    pub fn permalink(&self, message_ts: &str) -> anyhow::Result<String> {
+        let request = self.get(CHAT_PERMALINK_ENDPOINT).query(&[
+            ("channel", self.channel.as_str()),
+            ("message_ts", message_ts),
+        ]);
+        let response: PermalinkResponse = self.fetch(request)?;
+
+        match response.permalink {
+            Some(permalink) if response.ok => Ok(permalink),
+            _ => Err(api_error(response.error, response.needed)),
+        }
+    }
+
+    /// Return every message in a thread, starting with the parent message
+    pub fn replies(&self, thread_ts: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+        let mut chunk = self.get_reply_chunk(thread_ts, None)?;
+        let mut replies = Vec::new();
+
+        loop {
+            replies.extend(chunk.by_ref());
+
+            match chunk {
+                MessageChunk::Terminal { .. } => return Ok(replies),
+                MessageChunk::NonTerminal { next_cursor, .. } => {
+                    chunk = self.get_reply_chunk(thread_ts, Some(&next_cursor))?;
+                }
+            }
+        }
+    }
+
+    /// List the conversations visible to the token, limited to the provided types (e.g. `im`)
+    pub fn channels(&self, types: &[String]) -> anyhow::Result<Vec<serde_json::Value>> {
+        let types = types.join(",");
+
+        tracing::debug!(types = %types, "Listing conversations");
+
+        self.get_all_pages::<ChannelListResponse>(CONVERSATION_LIST_ENDPOINT, &[("types", &types)])
+    }
+
+    /// List the IDs of every member of the channel
+    pub fn members(&self) -> anyhow::Result<Vec<String>> {
+        tracing::debug!(channel = %self.channel, "Listing channel members");
+
+        self.get_all_pages::<MemberListResponse>(
+            CONVERSATION_MEMBERS_ENDPOINT,
+            &[("channel", &self.channel)],
+        )
+    }
+
+    /// List the workspace's users, mapping each ID to the name Slack displays for them
+    ///
+    /// That's the user's display name, falling back to their full name and then to their
+    /// username for users who never set one.
+    pub fn user_names(&self) -> anyhow::Result<HashMap<String, String>> {
+        tracing::debug!("Listing users");
+
+        let users = self.get_all_pages::<UserListResponse>(USER_LIST_ENDPOINT, &[])?;
+
+        Ok(users
+            .into_iter()
+            .filter_map(|user| {
+                let name = [&user.profile.display_name, &user.real_name, &user.name]
+                    .into_iter()
+                    .flatten()
+                    .find(|name| !name.is_empty())?;
+
+                Some((user.id, name.clone()))
+            })
+            .collect())
+    }
+
+    /// Fetch every page of a cursor-paginated list from a Slack API method
+    fn get_all_pages<P: Page>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, &str)],
+    ) -> anyhow::Result<Vec<P::Item>> {
+        let mut items = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let mut request = self
+                .get(endpoint)
+                .query(query)
+                .query(&[("limit", &RESPONSE_MESSAGE_LIMIT.to_string())]);
+
+            if let Some(cursor) = &cursor {
+                request = request.query(&[("cursor", cursor)]);
+            }
+
+            tracing::debug!(endpoint, has_cursor = cursor.is_some(), "Fetching a page");
+
+            let (page, next_cursor) = self.fetch::<P>(request)?.into_page()?;
+            items.extend(page);
+
+            cursor = next_cursor.filter(|next_cursor| !next_cursor.is_empty());
+
+            if cursor.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// The ID of the channel being exported
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// The configured `oldest` and `latest` Slack timestamps, if any
+    pub fn window(&self) -> (Option<&str>, Option<&str>) {
+        (self.bounds.oldest.as_deref(), self.bounds.latest.as_deref())
+    }
+
+    /// Count the requests made to the Slack API so far
+    pub fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests to the Slack API that were retried so far, whether after a
+    /// transient error or after being rate limited
+    pub fn retry_count(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// The time taken by each conversation history request so far, in the order they were made
+    pub fn history_latencies(&self) -> Vec<Duration> {
+        self.history_latencies
+            .lock()
+            .map(|history_latencies| history_latencies.clone())
+            .unwrap_or_default()
+    }
+
+    /// The rate limit and trace headers of the last response from Slack, if `CAPTURE_HEADERS` is
+    /// set
+    pub fn last_headers(&self) -> BTreeMap<String, String> {
+        self.last_headers
+            .lock()
+            .map(|last_headers| last_headers.clone())
+            .unwrap_or_default()
+    }
+
+    /// Return all of the messages from the conversation history API
+    ///
+    /// When a starting cursor is configured, the walk begins from that page instead of the newest.
+    pub fn messages(&self) -> anyhow::Result<Messages<'_>> {
+        if self.walk_by_day {
+            return self.walk_days();
+        }
+
+        let mut messages = match &self.checkpoint {
+            Some(checkpoint) => self.resume(checkpoint)?,
+            None => self.walk_from(self.bounds.clone(), self.start_cursor.as_ref())?,
+        };
+
+        // stop after the first page, however many more there are
+        if self.latest_only {
+            messages.current_chunk = messages.current_chunk.into_terminal();
+        }
+
+        Ok(messages)
+    }
+
+    /// Continue a walk from a checkpoint, falling back from its cursor to its last `ts`
+    fn resume(&self, checkpoint: &Checkpoint) -> anyhow::Result<Messages<'_>> {
+        if let Some(cursor) = &checkpoint.cursor {
+            match self.walk_from(self.bounds.clone(), Some(cursor)) {
+                Err(error) if checkpoint.last_ts.is_some() && is_invalid_cursor(&error) => {
+                    tracing::warn!(
+                        "Checkpoint cursor was rejected, restarting the walk before {:?}",
+                        checkpoint.last_ts
+                    );
+                }
+                messages => {
+                    return messages.map(|mut messages| {
+                        messages.last_ts = checkpoint.last_ts.clone();
+                        messages
+                    })
+                }
+            }
+        }
+
+        // the bound is exclusive, so the last message exported isn't fetched again
+        let latest = checkpoint
+            .last_ts
+            .clone()
+            .or_else(|| self.bounds.latest.clone());
+        let mut messages = self.walk(Bounds {
+            oldest: self.bounds.oldest.clone(),
+            latest,
+        })?;
+        messages.last_ts = checkpoint.last_ts.clone();
+
+        Ok(messages)
+    }
+
+    /// Walk through the conversation history one calendar day at a time, in the configured
+    /// direction
+    ///
+    /// Walking oldest first without an oldest date starts from the day the channel was created.
+    fn walk_days(&self) -> anyhow::Result<Messages<'_>> {
+        let oldest = match &self.bounds.oldest {
+            Some(oldest) => oldest.clone(),
+            None if self.direction == Direction::OldestFirst => self.created_ts()?,
+            None => anyhow::bail!("Walking the history by day requires an oldest date"),
+        };
+        let windows = dates::day_windows(&oldest, self.bounds.latest.as_deref(), self.timezone)
+            .ok_or_else(|| anyhow::anyhow!("Malformed date bounds: {:?}", self.bounds))?;
+
+        let mut days: VecDeque<_> = windows
+            .into_iter()
+            .map(|(oldest, latest)| Bounds {
+                oldest: Some(oldest),
+                latest: Some(latest),
+            })
+            .collect();
+
+        if self.direction == Direction::OldestFirst {
+            days.make_contiguous().reverse();
+        }
+
+        // an empty range still makes a single request, matching a regular walk
+        let first_day = days.pop_front().unwrap_or_else(|| self.bounds.clone());
+        let chunk = self.get_day_chunk(&first_day)?;
+        let mut messages = self.messages_from(first_day, chunk);
+        messages.remaining_days = days;
+
+        Ok(messages)
+    }
+
+    /// Return all of the messages, stopping at the next chunk boundary once `stop` is set
+    ///
+    /// Messages from chunks that were already fetched are still yielded before the walk ends.
+    pub fn messages_with_cancel(&self, stop: Arc<AtomicBool>) -> anyhow::Result<Messages<'_>> {
+        let mut messages = self.messages()?;
+        messages.stop = Some(stop);
+
+        Ok(messages)
+    }
+
+    /// Return all of the messages, fetching each page of the history on a thread in `scope` while
+    /// the page before it is still being consumed
+    ///
+    /// The thread stops once the returned messages are dropped, which happens before the scope
+    /// ends.
+    pub fn messages_with_lookahead<'scope>(
+        &'scope self,
+        scope: &'scope thread::Scope<'scope, '_>,
+    ) -> anyhow::Result<Messages<'scope>> {
+        let (requests, pending_requests) = mpsc::channel::<(Bounds, String)>();
+        let (fetched_chunks, chunks) = mpsc::channel();
+
+        scope.spawn(move || {
+            for (bounds, cursor) in pending_requests {
+                let chunk = self.get_message_chunk(&bounds, Some(&cursor));
+
+                if fetched_chunks.send(chunk).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut messages = self.messages()?;
+        messages.lookahead = Some(Lookahead {
+            requests,
+            chunks,
+            cursor: None,
+        });
+
+        Ok(messages)
+    }
+
+    /// Return the messages sent after the provided Slack timestamp (exclusive)
+    pub fn history_since(&self, oldest: &str) -> anyhow::Result<Messages<'_>> {
+        self.walk(Bounds {
+            oldest: Some(oldest.into()),
+            latest: self.bounds.latest.clone(),
+        })
+    }
+
+    /// Return the messages sent between two Slack timestamps, regardless of the configured dates
+    pub fn messages_between(&self, oldest: &str, latest: &str) -> anyhow::Result<Messages<'_>> {
+        self.walk(Bounds {
+            oldest: Some(oldest.into()),
+            latest: Some(latest.into()),
+        })
+    }
+
+    /// Return the messages in the channel matching a search query (e.g. `deploy from:@alex`),
+    /// newest first
+    ///
+    /// This uses the `search.messages` API, which requires a user token (not a bot token) with the
+    /// `search:read` scope. The configured dates and starting cursor don't apply to searches.
+    pub fn search(&self, query: &str) -> anyhow::Result<Messages<'_>> {
+        // limit the search to the channel with a search modifier
+        let query = format!("{} in:<#{}>", query, self.channel);
+        let (chunk, next_page) = self.get_search_page(&query, 1)?;
+
+        let mut messages = self.messages_from(Bounds::default(), chunk);
+        messages.search = Some(SearchPages { query, next_page });
+
+        Ok(messages)
+    }
+
+    /// Fetch a page of search results, along with the number of the next page if there is one
+    fn get_search_page(
+        &self,
+        query: &str,
+        page: u32,
+    ) -> anyhow::Result<(MessageChunk, Option<u32>)> {
+        let request = self.get(SEARCH_MESSAGES_ENDPOINT).query(&[
+            ("query", query),
+            ("sort", "timestamp"),
+            ("sort_dir", "desc"),
+            ("count", &SEARCH_RESULT_LIMIT.to_string()),
+            ("page", &page.to_string()),
+        ]);
+
+        tracing::debug!(page, "Fetching search results");
+
+        let response: SearchResponse = self.fetch(request)?;
+
+        if !response.ok {
+            // searching is one of the few methods that bot tokens can't use at all
+            if response.error.as_deref() == Some("not_allowed_token_type") {
+                anyhow::bail!(
+                    "Searching messages requires a user token with the search:read scope, not a bot token"
+                );
+            }
+
+            return Err(api_error(response.error, response.needed));
+        }
+
+        let results = response.messages.unwrap_or_default();
+        let next_page =
+            Some(results.paging.page + 1).filter(|next_page| *next_page <= results.paging.pages);
+        let chunk = MessageChunk::Terminal {
+            messages: results.matches.into_iter(),
+        };
+
+        Ok((chunk, next_page))
+    }
+
+    /// Start a paginated walk through the conversation history within a set of bounds
+    fn walk(&self, bounds: Bounds) -> anyhow::Result<Messages<'_>> {
+        self.walk_from(bounds, None)
+    }
+
+    /// Start a paginated walk through the conversation history from an optional cursor
+    fn walk_from(&self, bounds: Bounds, cursor: Option<&String>) -> anyhow::Result<Messages<'_>> {
+        let message_chunk = match self.get_message_chunk(&bounds, cursor) {
+            // cursors only work with the channel they came from, which Slack doesn't explain
+            Err(error) if cursor.is_some() && is_foreign_cursor_error(&error) => {
+                return Err(error.context(format!(
+                    "Slack rejected the start cursor for channel {}: it may belong to a different channel, or have expired",
+                    self.channel
+                )));
+            }
+            chunk => chunk?,
+        };
+
+        Ok(self.messages_from(bounds, message_chunk))
+    }
+
+    /// Start iterating over messages from a chunk that has already been fetched
+    fn messages_from(&self, bounds: Bounds, chunk: MessageChunk) -> Messages<'_> {
+        let messages = Messages {
+            client: self,
+            bounds,
+            current_chunk: chunk,
+            pending: VecDeque::new(),
+            threads_expanded: 0,
+            threads_truncated: false,
+            remaining_days: VecDeque::new(),
+            search: None,
+            last_ts: None,
+            stop: None,
+            lookahead: None,
+            pages: 1,
+        };
+        messages.record_page();
+
+        messages
+    }
+}
+
+// written by hand so that the token and authentication header are never printed
+impl fmt::Debug for Slack {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Slack")
+            .field("api_token", &redact(&self.api_token))
+            .field(
+                "auth_header",
+                &self
+                    .auth_header
+                    .as_ref()
+                    .map(|(name, _)| format!("{}: ***", name)),
+            )
+            .field("channel", &self.channel)
+            .field("base_url", &self.base_url)
+            .field("bounds", &self.bounds)
+            .field("include_all_metadata", &self.include_all_metadata)
+            .field("include_replies", &self.include_replies)
+            .field("max_thread_requests", &self.max_thread_requests)
+            .field("page_cap", &self.page_cap)
+            .field("history_limit", &self.history_limit)
+            .field("replies_limit", &self.replies_limit)
+            .field("start_cursor", &self.start_cursor)
+            .field("timezone", &self.timezone)
+            .field("walk_by_day", &self.walk_by_day)
+            .field("direction", &self.direction)
+            .field("recover_invalid_cursor", &self.recover_invalid_cursor)
+            .field("latest_only", &self.latest_only)
+            .field("nest_threads", &self.nest_threads)
+            .field("check_ordering", &self.check_ordering)
+            .field("requests", &self.request_count())
+            .field("retries", &self.retry_count())
+            .field("capture_headers", &self.capture_headers)
+            .field("replay", &self.replay.is_some())
+            .field("retry_if", &self.retry_if.is_some())
+            .field("deadline", &self.deadline)
+            .field(
+                "user_names",
+                &self.user_names.as_ref().map(|user_names| user_names.len()),
+            )
+            .field("progress", &self.progress.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TryFrom<Configuration> for Slack {
+    type Error = anyhow::Error;
+
+    fn try_from(configuration: Configuration) -> Result<Self, Self::Error> {
+        let client = Self::default_client(&configuration)?;
+
+        Self::with_client(configuration, client)
+    }
+}
+
+/// Parse a custom authentication header in the `Name: value` format
+fn parse_header(header: &str) -> anyhow::Result<(HeaderName, HeaderValue)> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Invalid auth header: expected the format `Name: value`"))?;
+
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| anyhow::anyhow!("Invalid auth header name: {}", name.trim()))?;
+    let mut value = HeaderValue::from_str(value.trim())
+        .map_err(|_| anyhow::anyhow!("Invalid auth header value for {}", name))?;
+
+    // keep credentials out of any debug output of the request
+    value.set_sensitive(true);
+
+    Ok((name, value))
+}
+
+/// Pick the root URL of the Slack API, without a trailing slash
+fn base_url(url: Option<String>) -> String {
+    url.as_deref()
+        .unwrap_or(DEFAULT_BASE_URL)
+        .trim_end_matches('/')
+        .into()
+}
+
+/// Clean up a user-provided channel ID, rejecting values that are clearly not an ID
+fn normalize_channel(channel: &str) -> anyhow::Result<String> {
+    // tolerate stray whitespace and a leading # copied from the Slack UI
+    let channel = channel.trim();
+    let channel = channel.strip_prefix('#').unwrap_or(channel);
+
+    let is_id = !channel.is_empty()
+        && channel
+            .chars()
+            .all(|character| character.is_ascii_uppercase() || character.is_ascii_digit());
+
+    if is_id {
+        return Ok(channel.into());
+    }
+
+    let is_name = !channel.is_empty()
+        && channel.chars().all(|character| {
+            character.is_ascii_lowercase()
+                || character.is_ascii_digit()
+                || character == '-'
+                || character == '_'
+        });
+
+    if is_name {
+        Err(anyhow::anyhow!(
+            "Channel {} looks like a channel name: provide the channel ID (e.g. C0123ABCD) instead",
+            channel
+        ))
+    } else {
+        Err(anyhow::anyhow!("Malformed channel ID: {:?}", channel))
+    }
+}
+
+/// Timestamp bounds applied to every request in a single walk
+#[derive(Clone, Debug, Default)]
+struct Bounds {
+    oldest: Option<String>,
+    latest: Option<String>,
+}
+
+impl Bounds {
+    /// Resolve the configured date range (or Unix timestamps) into Slack timestamps
+    fn resolve(
+        from_date: Option<String>,
+        to_date: Option<String>,
+        oldest: Option<f64>,
+        latest: Option<f64>,
+        timezone: Tz,
+    ) -> anyhow::Result<Self> {
+        let oldest = from_date
+            .map(|date| dates::to_slack_timestamp(&date, timezone))
+            .transpose()?
+            .or_else(|| oldest.map(dates::from_unix_timestamp));
+        let latest = to_date
+            .map(|date| dates::to_latest_timestamp(&date, timezone))
+            .transpose()?
+            .or_else(|| latest.map(dates::from_unix_timestamp));
+
+        Ok(Self { oldest, latest })
+    }
+}
+
+/// Redirect policy of the default clients, which refuses to follow redirects to another host
+fn redirect_policy() -> Policy {
+    Policy::custom(|attempt| {
+        let is_cross_host = attempt.previous().last().is_some_and(|previous| {
+            previous.host_str() != attempt.url().host_str()
+                || previous.port_or_known_default() != attempt.url().port_or_known_default()
+        });
+
+        if is_cross_host {
+            let error = format!(
+                "Slack API request was redirected to another host ({}), which would drop the token: send requests to that host directly instead",
+                attempt.url().host_str().unwrap_or_default()
+            );
+
+            attempt.error(error)
+        } else if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error(format!(
+                "Slack API request was redirected more than {} times",
+                MAX_REDIRECTS
+            ))
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Fallible iterator over messages from the Slack API
+///
+/// A page's messages are only yielded once its whole response has been received and parsed, and
+/// every retry happens before that, so a retried request can never yield a message twice.
+///
+/// Pages (and thread replies) are only fetched as their messages are asked for, so a consumer
+/// that stops pulling (e.g. one blocked on a full bounded channel) stops the requests too.
+pub struct Messages<'a> {
+    client: &'a Slack,
+    bounds: Bounds,
+    current_chunk: MessageChunk,
+    pending: VecDeque<serde_json::Value>,
+    threads_expanded: usize,
+    threads_truncated: bool,
+    remaining_days: VecDeque<Bounds>,
+    search: Option<SearchPages>,
+    last_ts: Option<String>,
+    stop: Option<Arc<AtomicBool>>,
+    lookahead: Option<Lookahead>,
+    pages: usize,
+}
+
+/// Requests for the next page of the history, fetched on another thread ahead of time
+struct Lookahead {
+    requests: mpsc::Sender<(Bounds, String)>,
+    chunks: mpsc::Receiver<anyhow::Result<MessageChunk>>,
+    cursor: Option<String>,
+}
+
+/// Progress through search results, which are paginated by page number rather than by cursor
+struct SearchPages {
+    query: String,
+    next_page: Option<u32>,
+}
+
+impl<'a> Messages<'a> {
+    /// Check whether every message in the conversation history has already been yielded
+    pub fn is_exhausted(&self) -> bool {
+        let chunk_is_exhausted = match &self.current_chunk {
+            MessageChunk::Terminal { messages } => messages.as_slice().is_empty(),
+            MessageChunk::NonTerminal { .. } => false,
+        };
+
+        let search_is_exhausted = self
+            .search
+            .as_ref()
+            .is_none_or(|search| search.next_page.is_none());
+
+        chunk_is_exhausted
+            && self.pending.is_empty()
+            && self.remaining_days.is_empty()
+            && search_is_exhausted
+    }
+
+    /// Describe how far the walk has got, for resuming it later
+    ///
+    /// The cursor is only included between pages, once every message (and queued reply) of the
+    /// current page has been returned.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let cursor = match &self.current_chunk {
+            MessageChunk::NonTerminal { next_cursor, .. }
+                if self.pending.is_empty() && !self.current_chunk.has_remaining() =>
+            {
+                Some(next_cursor.clone())
+            }
+            _ => None,
+        };
+
+        Checkpoint {
+            cursor,
+            last_ts: self.last_ts.clone(),
+            output_len: None,
+        }
+    }
+
+    /// Number of pages fetched by the walk so far, counting the page being yielded
+    ///
+    /// Only the pages of the history (or of search results) are counted, not requests for thread
+    /// replies or requests that were retried.
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Return the rest of the current page of messages as a batch, advancing to the next page
+    ///
+    /// Thread replies are included after their parents just like when iterating message by
+    /// message, and empty pages are skipped over.
+    pub fn next_page(&mut self) -> anyhow::Result<Option<Vec<serde_json::Value>>> {
+        let mut page = match self.try_next()? {
+            Some(message) => vec![message],
+            None => return Ok(None),
+        };
+
+        // drain the replies of the page's last thread, but never pull in the next page (which
+        // skipping replies already nested under their parent would otherwise do)
+        loop {
+            if let Some(reply) = self.pending.pop_front() {
+                page.push(reply);
+                continue;
+            }
+
+            if !self.current_chunk.has_remaining() {
+                return Ok(Some(page));
+            }
+
+            if let Some(message) = self.next_from_history()? {
+                page.extend(self.with_thread(message)?);
+            }
+        }
+    }
+
+    /// Regroup the messages into batches of `size`, regardless of how Slack paginates them
+    ///
+    /// Every batch is full except possibly the last one. Panics if `size` is zero.
+    pub fn batches(self, size: usize) -> Batches<'a> {
+        assert!(size > 0, "batch size must be at least 1");
+
+        Batches {
+            messages: self,
+            size,
+        }
+    }
+
+    /// Filter and transform the messages in a single streaming pass, keeping each message for
+    /// which `f` returns `Some` (replaced by what it returns) and dropping the rest
+    ///
+    /// Thread replies are passed through `f` just like top-level messages.
+    pub fn filter_map_value<F>(self, f: F) -> FilterMapValue<'a, F>
+    where
+        F: Fn(serde_json::Value) -> Option<serde_json::Value>,
+    {
+        FilterMapValue { messages: self, f }
+    }
+
+    /// Call `f` on each message as it's yielded, without changing the stream, e.g. to count
+    /// messages or update metrics
+    ///
+    /// Thread replies are passed to `f` just like top-level messages.
+    pub fn inspect<F>(self, f: F) -> Inspect<'a, F>
+    where
+        F: Fn(&serde_json::Value),
+    {
+        Inspect { messages: self, f }
+    }
+
+    /// Pull the next top-level message from the conversation history, fetching chunks as needed
+    fn next_from_history(&mut self) -> anyhow::Result<Option<serde_json::Value>> {
+        loop {
+            self.prefetch();
+
+            if let Some(message) = self.current_chunk.next() {
+                if let Some(ts) = message.get("ts").and_then(serde_json::Value::as_str) {
+                    if self.client.check_ordering {
+                        self.check_order(ts);
+                    }
+
+                    self.last_ts = Some(ts.to_owned());
+                }
+
+                return Ok(Some(message));
+            }
+
+            let has_more = match &self.current_chunk {
+                MessageChunk::NonTerminal { .. } => true,
+                MessageChunk::Terminal { .. } => {
+                    !self.remaining_days.is_empty()
+                        || self
+                            .search
+                            .as_ref()
+                            .is_some_and(|search| search.next_page.is_some())
+                }
+            };
+
+            if has_more {
+                if self.reached_page_cap() {
+                    self.finish_progress();
+                    return Ok(None);
+                }
+
+                self.pages += 1;
+            }
+
+            match &self.current_chunk {
+                // move on to the next page of search results, or to the next day's pages when
+                // walking the history by day
+                MessageChunk::Terminal { .. } if self.is_cancelled() => {
+                    self.finish_progress();
+                    return Ok(None);
+                }
+                MessageChunk::Terminal { .. } if self.search.is_some() => {
+                    let search = self.search.as_mut().expect("searching");
+
+                    match search.next_page {
+                        Some(page) => {
+                            let (chunk, next_page) =
+                                self.client.get_search_page(&search.query, page)?;
+                            self.current_chunk = chunk;
+                            search.next_page = next_page;
+                        }
+                        None => {
+                            self.finish_progress();
+                            return Ok(None);
+                        }
+                    }
+                }
+                MessageChunk::Terminal { .. } => match self.remaining_days.pop_front() {
+                    Some(bounds) => {
+                        self.current_chunk = self.client.get_day_chunk(&bounds)?;
+                        self.bounds = bounds;
+                    }
+                    None => {
+                        self.finish_progress();
+                        return Ok(None);
+                    }
+                },
+                MessageChunk::NonTerminal { .. } if self.is_cancelled() => {
+                    tracing::debug!("Export cancelled, not fetching any more history");
+                    self.finish_progress();
+                    return Ok(None);
+                }
+                MessageChunk::NonTerminal { next_cursor, .. } => {
+                    let next_cursor = next_cursor.clone();
+
+                    self.current_chunk = match self.fetch_next_chunk(&next_cursor) {
+                        // the history is walked newest first, so everything older than the
+                        // last message yielded is still to come (and the bound is exclusive)
+                        Err(error)
+                            if self.client.recover_invalid_cursor && is_invalid_cursor(&error) =>
+                        {
+                            // without a message to restart from, the walk would start over from
+                            // the beginning (and run into the same cursor again)
+                            let Some(last_ts) = self.last_ts.clone() else {
+                                return Err(error.context(
+                                    "Pagination cursor expired before any message was exported",
+                                ));
+                            };
+
+                            tracing::warn!(
+                                "Pagination cursor expired, restarting the walk before {}",
+                                last_ts
+                            );
+
+                            self.bounds.latest = Some(last_ts);
+                            self.client.get_message_chunk(&self.bounds, None)?
+                        }
+                        chunk => chunk?,
+                    };
+                }
+            }
+
+            // every arm that didn't end the walk fetched exactly one more page
+            self.record_page();
+        }
+    }
+
+    /// Count the page that was just fetched towards `PROGRESS`, if it's being reported
+    fn record_page(&self) {
+        let Some(progress) = &self.client.progress else {
+            return;
+        };
+
+        let messages = match &self.current_chunk {
+            MessageChunk::NonTerminal { messages, .. } | MessageChunk::Terminal { messages } => {
+                messages.as_slice().len()
+            }
+        };
+
+        if let Ok(mut progress) = progress.lock() {
+            progress.record_page(messages);
+        }
+    }
+
+    /// End the `PROGRESS` line with a summary, once the walk has reached its last page (or stopped
+    /// early)
+    fn finish_progress(&self) {
+        if let Some(Ok(mut progress)) = self
+            .client
+            .progress
+            .as_ref()
+            .map(|progress| progress.lock())
+        {
+            progress.finish();
+        }
+    }
+
+    /// Start fetching the chunk after the current one in the background, if it isn't already
+    fn prefetch(&mut self) {
+        let (Some(lookahead), MessageChunk::NonTerminal { next_cursor, .. }) =
+            (&mut self.lookahead, &self.current_chunk)
+        else {
+            return;
+        };
+
+        if lookahead.cursor.is_none()
+            && lookahead
+                .requests
+                .send((self.bounds.clone(), next_cursor.clone()))
+                .is_ok()
+        {
+            lookahead.cursor = Some(next_cursor.clone());
+        }
+    }
+
+    /// Fetch the chunk at a cursor, waiting for a prefetch of it if one was started
+    fn fetch_next_chunk(&mut self, cursor: &String) -> anyhow::Result<MessageChunk> {
+        if let Some(lookahead) = &mut self.lookahead {
+            // a prefetch of any other cursor is stale, but still has to be taken off the channel
+            if let Some(requested) = lookahead.cursor.take() {
+                match lookahead.chunks.recv() {
+                    Ok(chunk) if &requested == cursor => return chunk,
+                    _ => (),
+                }
+            }
+        }
+
+        self.client.get_message_chunk(&self.bounds, Some(cursor))
+    }
+
+    /// Warn when a message breaks the walk's order, e.g. when it's newer than the one before it
+    /// in Slack's usual newest-first order
+    fn check_order(&self, ts: &str) {
+        let previous = self
+            .last_ts
+            .as_deref()
+            .and_then(dates::from_slack_timestamp);
+        let current = dates::from_slack_timestamp(ts);
+
+        let is_out_of_order = previous.is_some_and(|previous| match self.client.direction {
+            Direction::NewestFirst => current > Some(previous),
+            Direction::OldestFirst => current < Some(previous),
+        });
+
+        if is_out_of_order {
+            tracing::warn!(
+                "Slack returned message {} out of order, after message {:?}",
+                ts,
+                self.last_ts
+            );
+        }
+    }
+
+    /// Check whether the walk has fetched as many pages as the safety cap allows, warning that the
+    /// export is incomplete if so
+    fn reached_page_cap(&self) -> bool {
+        let page_cap = match self.client.page_cap {
+            Some(page_cap) if self.pages >= page_cap => page_cap,
+            _ => return false,
+        };
+
+        tracing::warn!(
+            "Stopped after PAGE_CAP={} pages of history with more to fetch: THE EXPORT IS INCOMPLETE",
+            page_cap
+        );
+
+        true
+    }
+
+    /// Check whether the caller has asked for the walk to stop, or its deadline has passed
+    fn is_cancelled(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || self.client.is_past_deadline()
+    }
+
+    /// Expand a top-level message's thread when replies are included, or drop a reply that's
+    /// already nested under its parent
+    fn with_thread(
+        &mut self,
+        message: serde_json::Value,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        if self.client.include_replies && is_thread_parent(&message) {
+            return self.expand_thread(message).map(Some);
+        }
+
+        // replies broadcast to the channel are already nested under their parent
+        if self.client.nest_threads && is_thread_reply(&message) {
+            return Ok(None);
+        }
+
+        Ok(Some(message))
+    }
+
+    /// Expand a thread parent into the full thread, queueing the replies behind the parent
+    ///
+    /// The replies API returns its own copy of the parent message first, which is preferred over
+    /// the copy from the conversation history since it reflects the thread as a whole.
+    fn expand_thread(
+        &mut self,
+        mut parent: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let thread_ts = match parent.get("ts").and_then(serde_json::Value::as_str) {
+            Some(thread_ts) => thread_ts.to_owned(),
+            None => return Ok(parent),
+        };
+
+        // keep the parent but mark its replies as missing once the thread budget is spent
+        let max_thread_requests = self.client.max_thread_requests;
+
+        if let Some(max_thread_requests) =
+            max_thread_requests.filter(|max| self.threads_expanded >= *max)
+        {
+            if !self.threads_truncated {
+                tracing::warn!(
+                    "Reached the limit of {} threads, omitting replies to the remaining threads",
+                    max_thread_requests
+                );
+                self.threads_truncated = true;
+            }
+
+            if let Some(parent) = parent.as_object_mut() {
+                parent.insert("replies_truncated".into(), true.into());
+            }
+
+            return Ok(parent);
+        }
+
+        self.threads_expanded += 1;
+
+        let mut thread = self.client.replies(&thread_ts)?.into_iter().peekable();
+
+        let replies_parent = thread.next_if(|message| message.get("ts") == parent.get("ts"));
+        let mut parent = match replies_parent {
+            Some(replies_parent) => with_missing_fields(replies_parent, parent),
+            None => parent,
+        };
+
+        // either nest the replies under their parent or emit them right after it
+        match parent.as_object_mut() {
+            Some(fields) if self.client.nest_threads => {
+                fields.insert("thread".into(), thread.collect::<Vec<_>>().into());
+            }
+            _ => self.pending.extend(thread),
+        }
+
+        Ok(parent)
+    }
+}
+
+impl Messages<'_> {
+    /// Return the next message, fetching more of the history (and thread replies) as needed
+    pub fn try_next(&mut self) -> anyhow::Result<Option<serde_json::Value>> {
+        if let Some(reply) = self.pending.pop_front() {
+            return Ok(Some(reply));
+        }
+
+        while let Some(message) = self.next_from_history()? {
+            if let Some(message) = self.with_thread(message)? {
+                return Ok(Some(message));
+            }
+        }
 
-/// Non-configurable static values for the Slack API
-static CONVERSATION_HISTORY_ENDPOINT: &str = "https://slack.com/api/conversations.history";
-static RESPONSE_MESSAGE_LIMIT: i16 = 1000;
+        Ok(None)
+    }
+}
 
-/// Top-level Slack API client for a single channel
-pub struct Slack {
-    api_token: String,
-    channel: String,
-    client: Client,
+#[cfg(feature = "fallible-iterator")]
+impl<'a> FallibleIterator for Messages<'a> {
+    type Item = serde_json::Value;
+    type Error = anyhow::Error;
+
+    fn next(&mut self) -> Result<Option<Self::Item>, Self::Error> {
+        self.try_next()
+    }
 }
 
-impl Slack {
-    /// Fetch a single chunk of messages from the conversation history API
-    fn get_message_chunk(&self, cursor: Option<&String>) -> anyhow::Result<MessageChunk> {
-        let mut request = self
-            .client
-            .get(CONVERSATION_HISTORY_ENDPOINT)
-            .bearer_auth(&self.api_token);
+/// Without `fallible-iterator`, messages are yielded as a standard iterator of `Result`s
+#[cfg(not(feature = "fallible-iterator"))]
+impl Iterator for Messages<'_> {
+    type Item = anyhow::Result<serde_json::Value>;
 
-        if let Some(cursor) = cursor {
-            request = request.query(&[
-                ("channel", &self.channel),
-                ("limit", &RESPONSE_MESSAGE_LIMIT.to_string()),
-                ("cursor", cursor),
-            ]);
-        } else {
-            request = request.query(&[
-                ("channel", &self.channel),
-                ("limit", &RESPONSE_MESSAGE_LIMIT.to_string()),
-            ]);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// Fallible iterator over fixed-size batches of messages, e.g. for bulk inserts into a database
+pub struct Batches<'a> {
+    messages: Messages<'a>,
+    size: usize,
+}
+
+impl Batches<'_> {
+    /// Return the next batch, fetching as many pages as it takes to fill it
+    pub fn try_next(&mut self) -> anyhow::Result<Option<Vec<serde_json::Value>>> {
+        let mut batch = Vec::with_capacity(self.size);
+
+        while batch.len() < self.size {
+            match self.messages.try_next()? {
+                Some(message) => batch.push(message),
+                None => break,
+            }
         }
 
-        request.send()?.json::<Response>()?.try_into()
+        Ok(Some(batch).filter(|batch| !batch.is_empty()))
     }
+}
 
-    /// Return all of the messages from the conversation history API
-    pub fn messages(&self) -> anyhow::Result<Messages> {
-        let message_chunk = self.get_message_chunk(None)?;
+#[cfg(feature = "fallible-iterator")]
+impl FallibleIterator for Batches<'_> {
+    type Item = Vec<serde_json::Value>;
+    type Error = anyhow::Error;
 
-        Ok(Messages {
-            client: self,
-            current_chunk: message_chunk,
-        })
+    fn next(&mut self) -> Result<Option<Self::Item>, Self::Error> {
+        self.try_next()
     }
 }
 
-impl From<Configuration> for Slack {
-    fn from(configuration: Configuration) -> Self {
-        let Configuration { api_token, channel } = configuration;
-        let client = Client::new();
+#[cfg(not(feature = "fallible-iterator"))]
+impl Iterator for Batches<'_> {
+    type Item = anyhow::Result<Vec<serde_json::Value>>;
 
-        Self {
-            api_token,
-            channel,
-            client,
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// Fallible iterator over the messages that a closure keeps, as transformed by that closure
+pub struct FilterMapValue<'a, F> {
+    messages: Messages<'a>,
+    f: F,
+}
+
+impl<F> FilterMapValue<'_, F>
+where
+    F: Fn(serde_json::Value) -> Option<serde_json::Value>,
+{
+    /// Return the next message that the closure keeps, skipping over any that it drops
+    pub fn try_next(&mut self) -> anyhow::Result<Option<serde_json::Value>> {
+        while let Some(message) = self.messages.try_next()? {
+            if let Some(message) = (self.f)(message) {
+                return Ok(Some(message));
+            }
         }
+
+        Ok(None)
     }
 }
 
-/// Fallible iterator over messages from the Slack API
-pub struct Messages<'a> {
-    client: &'a Slack,
-    current_chunk: MessageChunk,
+#[cfg(feature = "fallible-iterator")]
+impl<F> FallibleIterator for FilterMapValue<'_, F>
+where
+    F: Fn(serde_json::Value) -> Option<serde_json::Value>,
+{
+    type Item = serde_json::Value;
+    type Error = anyhow::Error;
+
+    fn next(&mut self) -> Result<Option<Self::Item>, Self::Error> {
+        self.try_next()
+    }
 }
 
-impl<'a> FallibleIterator for Messages<'a> {
+#[cfg(not(feature = "fallible-iterator"))]
+impl<F> Iterator for FilterMapValue<'_, F>
+where
+    F: Fn(serde_json::Value) -> Option<serde_json::Value>,
+{
+    type Item = anyhow::Result<serde_json::Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// Fallible iterator over the messages, passing each one to a closure before yielding it
+pub struct Inspect<'a, F> {
+    messages: Messages<'a>,
+    f: F,
+}
+
+impl<F> Inspect<'_, F>
+where
+    F: Fn(&serde_json::Value),
+{
+    /// Return the next message, once the closure has seen it
+    pub fn try_next(&mut self) -> anyhow::Result<Option<serde_json::Value>> {
+        let message = self.messages.try_next()?;
+
+        if let Some(message) = &message {
+            (self.f)(message);
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(feature = "fallible-iterator")]
+impl<F> FallibleIterator for Inspect<'_, F>
+where
+    F: Fn(&serde_json::Value),
+{
     type Item = serde_json::Value;
     type Error = anyhow::Error;
 
     fn next(&mut self) -> Result<Option<Self::Item>, Self::Error> {
-        match self.current_chunk.next() {
-            Some(message) => Ok(Some(message)),
-            None => match &self.current_chunk {
-                MessageChunk::Terminal { .. } => Ok(None),
-                MessageChunk::NonTerminal { next_cursor, .. } => {
-                    self.current_chunk = self.client.get_message_chunk(Some(next_cursor))?;
-                    Ok(self.current_chunk.next())
-                }
-            },
+        self.try_next()
+    }
+}
+
+#[cfg(not(feature = "fallible-iterator"))]
+impl<F> Iterator for Inspect<'_, F>
+where
+    F: Fn(&serde_json::Value),
+{
+    type Item = anyhow::Result<serde_json::Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// Fill in any fields of a message that only another copy of it has (e.g. `pinned_to` and
+/// `pin_count`, which the replies API may leave out of a thread parent)
+fn with_missing_fields(
+    mut message: serde_json::Value,
+    other: serde_json::Value,
+) -> serde_json::Value {
+    if let (Some(fields), serde_json::Value::Object(other)) = (message.as_object_mut(), other) {
+        for (key, value) in other {
+            fields.entry(key).or_insert(value);
         }
     }
+
+    message
+}
+
+/// Check whether a message starts a thread with at least one reply
+fn is_thread_parent(message: &serde_json::Value) -> bool {
+    let has_replies = message
+        .get("reply_count")
+        .and_then(serde_json::Value::as_u64)
+        .is_some_and(|reply_count| reply_count > 0);
+
+    has_replies && message.get("thread_ts") == message.get("ts")
+}
+
+/// Check whether a message is a reply in a thread rather than the start of one
+fn is_thread_reply(message: &serde_json::Value) -> bool {
+    let thread_ts = message.get("thread_ts");
+
+    thread_ts.is_some() && thread_ts != message.get("ts")
 }
 
 /// Processed chunk of messages from the Slack API
@@ -97,6 +2107,26 @@ enum MessageChunk {
     },
 }
 
+impl MessageChunk {
+    /// Drop the cursor to the next chunk, making this the last chunk of the walk
+    fn into_terminal(self) -> Self {
+        match self {
+            Self::NonTerminal { messages, .. } | Self::Terminal { messages } => {
+                Self::Terminal { messages }
+            }
+        }
+    }
+
+    /// Check whether any messages in the chunk have yet to be yielded
+    fn has_remaining(&self) -> bool {
+        match self {
+            Self::NonTerminal { messages, .. } | Self::Terminal { messages } => {
+                !messages.as_slice().is_empty()
+            }
+        }
+    }
+}
+
 impl Iterator for MessageChunk {
     type Item = serde_json::Value;
 
@@ -114,12 +2144,12 @@ impl TryFrom<Response> for MessageChunk {
     fn try_from(response: Response) -> Result<Self, Self::Error> {
         // guard against general error responses from the API
         if !response.ok {
-            let error = response.error.unwrap_or_else(|| "Unknown".into());
+            return Err(api_error(response.error, response.needed));
+        }
 
-            return Err(anyhow::anyhow!(
-                "Error fetching data from the Slack API: {}",
-                error
-            ));
+        // surface non-fatal issues that Slack reports alongside successful responses
+        if let Some(warning) = &response.warning {
+            tracing::warn!("Slack API responded with a warning: {}", warning);
         }
 
         // guard against invalid cursor values
@@ -128,6 +2158,17 @@ impl TryFrom<Response> for MessageChunk {
                 anyhow::anyhow!("Error fetching additional data: Slack API response missing cursor")
             })?;
 
+            // without a cursor there's no way to continue, so stop rather than fail outright
+            if metadata.next_cursor.is_empty() {
+                tracing::warn!(
+                    "Slack API reported more messages without a cursor to fetch them, stopping early"
+                );
+
+                return Ok(Self::Terminal {
+                    messages: response.messages.into_iter(),
+                });
+            }
+
             Self::NonTerminal {
                 messages: response.messages.into_iter(),
                 next_cursor: metadata.next_cursor,
@@ -142,6 +2183,81 @@ impl TryFrom<Response> for MessageChunk {
     }
 }
 
+/// Convert the error code (and any scope it was missing) from an unsuccessful Slack API response
+/// into an error
+fn api_error(error: Option<String>, needed: Option<String>) -> anyhow::Error {
+    let error = error.unwrap_or_else(|| "Unknown".into());
+
+    if error == "missing_scope" {
+        return Error::MissingScope {
+            needed,
+            provided: None,
+            accepted: None,
+        }
+        .into();
+    }
+
+    if AUTH_ERRORS.contains(&error.as_str()) {
+        return Error::Auth { error }.into();
+    }
+
+    Error::Api { error }.into()
+}
+
+/// Check whether a request failed because the host name couldn't be resolved
+fn is_dns_error(error: &anyhow::Error) -> bool {
+    // hyper doesn't expose its resolution errors as a type, only through their message
+    error
+        .chain()
+        .any(|cause| cause.to_string().starts_with("dns error"))
+}
+
+/// Parse the raw body of a response directly, so that unparseable responses can be included in
+/// errors
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> anyhow::Result<T> {
+    serde_json::from_slice(body).map_err(|error| {
+        let snippet_length = body.len().min(ERROR_BODY_SNIPPET_LENGTH);
+
+        anyhow::anyhow!(
+            "Error parsing Slack API response: {} (response began with: {:?})",
+            error,
+            String::from_utf8_lossy(&body[..snippet_length])
+        )
+    })
+}
+
+/// Read how long a rate limited response asked to wait before retrying, from its `Retry-After`
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Check whether a response header is one of those kept with `CAPTURE_HEADERS`
+fn is_captured_header(name: &HeaderName) -> bool {
+    CAPTURED_HEADER_PREFIXES
+        .iter()
+        .any(|prefix| name.as_str().starts_with(prefix))
+}
+
+/// Check whether an error is Slack rejecting an expired or otherwise invalid pagination cursor
+fn is_invalid_cursor(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<Error>(),
+        Some(Error::Api { error }) if error == "invalid_cursor"
+    )
+}
+
+/// Check whether an error is how Slack rejects a cursor from another channel's history
+fn is_foreign_cursor_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<Error>(),
+        Some(Error::Api { error }) if error == "invalid_cursor" || error == "channel_not_found"
+    )
+}
+
 /// Slack-specific API responses
 #[derive(Debug, Deserialize)]
 struct Response {
@@ -154,9 +2270,244 @@ struct Response {
     response_metadata: Option<ResponseMetadata>,
     #[serde(default)]
     error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+    #[serde(default)]
+    warning: Option<String>,
+}
+
+/// The fields of any Slack API response that describe a failure
+#[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    ok: bool,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+    /// Scopes the token has, for `missing_scope` errors
+    #[serde(default)]
+    provided: Option<String>,
+}
+
+/// OAuth scopes reported in the headers of a response, for explaining `missing_scope` errors
+#[derive(Debug, Default)]
+struct OauthScopes {
+    provided: Option<String>,
+    accepted: Option<String>,
+}
+
+impl OauthScopes {
+    /// Read the scope headers of a response, which Slack only sends for some tokens and methods
+    fn from_headers(response: &HttpResponse) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        Self {
+            provided: header(OAUTH_SCOPES_HEADER),
+            accepted: header(ACCEPTED_OAUTH_SCOPES_HEADER),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct ResponseMetadata {
+    #[serde(default)]
     next_cursor: String,
 }
+
+/// Slack-specific API responses for channel metadata
+#[derive(Debug, Deserialize)]
+struct ChannelInfoResponse {
+    ok: bool,
+    #[serde(default)]
+    channel: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+/// Slack-specific API responses for checking that the API can be reached
+#[derive(Debug, Deserialize)]
+struct ApiTestResponse {
+    ok: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Slack-specific API responses for the workspace's custom emoji
+#[derive(Debug, Deserialize)]
+struct EmojiListResponse {
+    ok: bool,
+    #[serde(default)]
+    emoji: Option<HashMap<String, String>>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+/// Responses that hold a single page of a cursor-paginated list
+trait Page: DeserializeOwned {
+    type Item;
+
+    /// Split the response into its items and the cursor to the next page, if it succeeded
+    fn into_page(self) -> anyhow::Result<(Vec<Self::Item>, Option<String>)>;
+}
+
+/// Slack-specific API responses for lists of conversations
+#[derive(Debug, Deserialize)]
+struct ChannelListResponse {
+    ok: bool,
+    #[serde(default)]
+    channels: Vec<serde_json::Value>,
+    #[serde(default)]
+    response_metadata: Option<ResponseMetadata>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+impl Page for ChannelListResponse {
+    type Item = serde_json::Value;
+
+    fn into_page(self) -> anyhow::Result<(Vec<Self::Item>, Option<String>)> {
+        if !self.ok {
+            return Err(api_error(self.error, self.needed));
+        }
+
+        let next_cursor = self.response_metadata.map(|metadata| metadata.next_cursor);
+
+        Ok((self.channels, next_cursor))
+    }
+}
+
+/// Slack-specific API responses for lists of channel members
+#[derive(Debug, Deserialize)]
+struct MemberListResponse {
+    ok: bool,
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    response_metadata: Option<ResponseMetadata>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+impl Page for MemberListResponse {
+    type Item = String;
+
+    fn into_page(self) -> anyhow::Result<(Vec<Self::Item>, Option<String>)> {
+        if !self.ok {
+            return Err(api_error(self.error, self.needed));
+        }
+
+        let next_cursor = self.response_metadata.map(|metadata| metadata.next_cursor);
+
+        Ok((self.members, next_cursor))
+    }
+}
+
+/// Slack-specific API responses for lists of users
+#[derive(Debug, Deserialize)]
+struct UserListResponse {
+    ok: bool,
+    #[serde(default)]
+    members: Vec<User>,
+    #[serde(default)]
+    response_metadata: Option<ResponseMetadata>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+impl Page for UserListResponse {
+    type Item = User;
+
+    fn into_page(self) -> anyhow::Result<(Vec<Self::Item>, Option<String>)> {
+        if !self.ok {
+            return Err(api_error(self.error, self.needed));
+        }
+
+        let next_cursor = self.response_metadata.map(|metadata| metadata.next_cursor);
+
+        Ok((self.members, next_cursor))
+    }
+}
+
+/// The names of a single user from `users.list`
+#[derive(Debug, Deserialize)]
+struct User {
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    real_name: Option<String>,
+    #[serde(default)]
+    profile: UserProfile,
+}
+
+/// The part of a user's profile with the name shown in Slack
+#[derive(Debug, Default, Deserialize)]
+struct UserProfile {
+    #[serde(default)]
+    display_name: Option<String>,
+}
+
+/// Slack-specific API responses for message permalinks
+#[derive(Debug, Deserialize)]
+struct PermalinkResponse {
+    ok: bool,
+    #[serde(default)]
+    permalink: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+/// Slack-specific API responses for message searches
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    ok: bool,
+    #[serde(default)]
+    messages: Option<SearchResults>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Scope the token was missing, for `missing_scope` errors
+    #[serde(default)]
+    needed: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SearchResults {
+    #[serde(default)]
+    matches: Vec<serde_json::Value>,
+    #[serde(default)]
+    paging: SearchPaging,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SearchPaging {
+    #[serde(default)]
+    page: u32,
+    #[serde(default)]
+    pages: u32,
+}
@@ -0,0 +1,175 @@
+use super::{
+    base_url, normalize_channel, parse_body, parse_header, redirect_policy, retry_after, Bounds,
+    MessageChunk, Response, CONVERSATION_HISTORY_ENDPOINT,
+};
+use crate::{Configuration, Error};
+use futures_util::stream::{self, Stream};
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Client, RequestBuilder, StatusCode,
+};
+use std::time::Duration;
+
+/// Asynchronous Slack API client, streaming a channel's history without blocking the runtime
+/// it's polled on (e.g. inside a tokio service)
+///
+/// Only the history itself is streamed, with the same bounds, page size and authentication as
+/// the blocking [`crate::Slack`]. Requests are neither paced nor retried: a failed request ends
+/// the stream with its error (e.g. `Error::RateLimited` with Slack's `retry_after`), so that the
+/// caller can wait on its own runtime's timer before streaming again.
+pub struct AsyncSlack {
+    api_token: String,
+    auth_header: Option<(HeaderName, HeaderValue)>,
+    channel: String,
+    client: Client,
+    base_url: String,
+    bounds: Bounds,
+    history_limit: i16,
+}
+
+/// Progress through the history: the next page to request, or the page being yielded
+enum Walk {
+    Fetch(Option<String>),
+    Yield(MessageChunk),
+}
+
+impl TryFrom<Configuration> for AsyncSlack {
+    type Error = anyhow::Error;
+
+    fn try_from(configuration: Configuration) -> Result<Self, Self::Error> {
+        let client = Self::default_client(&configuration)?;
+
+        Self::with_client(configuration, client)
+    }
+}
+
+impl AsyncSlack {
+    /// Create the async HTTP client used unless one is provided, with the same redirect policy
+    /// and connection pool settings as [`crate::Slack::default_client`]
+    pub fn default_client(configuration: &Configuration) -> anyhow::Result<Client> {
+        let mut builder = Client::builder().redirect(redirect_policy());
+
+        if let Some(secs) = configuration.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+        }
+
+        if let Some(max_idle) = configuration.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Create an async Slack API client that shares a pre-configured HTTP client
+    pub fn with_client(configuration: Configuration, client: Client) -> anyhow::Result<Self> {
+        let timezone = configuration.timezone()?;
+        let history_limit = configuration.history_limit();
+        let Configuration {
+            api_token,
+            auth_header,
+            slack_base_url,
+            channel,
+            from_date,
+            to_date,
+            oldest,
+            latest,
+            ..
+        } = configuration;
+
+        Ok(Self {
+            api_token,
+            auth_header: auth_header.as_deref().map(parse_header).transpose()?,
+            channel: normalize_channel(&channel)?,
+            client,
+            base_url: base_url(slack_base_url),
+            bounds: Bounds::resolve(from_date, to_date, oldest, latest, timezone)?,
+            history_limit,
+        })
+    }
+
+    /// Send every request to another root URL (e.g. a mock server) instead of the configured one
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into().trim_end_matches('/').into();
+        self
+    }
+
+    /// Stream every message of the history, newest first
+    ///
+    /// Each page is only requested once every message of the page before it has been taken, so
+    /// a consumer that stops polling (e.g. one waiting on a full bounded channel) stops the
+    /// requests too. The stream ends after the first error.
+    pub fn messages(&self) -> impl Stream<Item = anyhow::Result<serde_json::Value>> + '_ {
+        stream::try_unfold(Walk::Fetch(None), move |mut walk| async move {
+            loop {
+                walk = match walk {
+                    Walk::Fetch(cursor) => {
+                        Walk::Yield(self.get_message_chunk(cursor.as_deref()).await?)
+                    }
+                    Walk::Yield(mut chunk) => match chunk.next() {
+                        Some(message) => return Ok(Some((message, Walk::Yield(chunk)))),
+                        None => match chunk {
+                            MessageChunk::NonTerminal { next_cursor, .. } => {
+                                Walk::Fetch(Some(next_cursor))
+                            }
+                            MessageChunk::Terminal { .. } => return Ok(None),
+                        },
+                    },
+                };
+            }
+        })
+    }
+
+    /// Fetch a single chunk of messages from the conversation history API
+    async fn get_message_chunk(&self, cursor: Option<&str>) -> anyhow::Result<MessageChunk> {
+        let mut request = self.get(CONVERSATION_HISTORY_ENDPOINT).query(&[
+            ("channel", self.channel.as_str()),
+            ("limit", &self.history_limit.to_string()),
+        ]);
+
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+
+        if let Some(oldest) = &self.bounds.oldest {
+            request = request.query(&[("oldest", oldest)]);
+        }
+
+        if let Some(latest) = &self.bounds.latest {
+            request = request.query(&[("latest", latest)]);
+        }
+
+        tracing::debug!(
+            channel = %self.channel,
+            has_cursor = cursor.is_some(),
+            "Fetching conversation history"
+        );
+
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_server_error() {
+            return Err(Error::Server { status }.into());
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Error::RateLimited {
+                retry_after: retry_after(response.headers()),
+            }
+            .into());
+        }
+
+        let body = response.bytes().await?;
+
+        parse_body::<Response>(&body)?.try_into()
+    }
+
+    /// Build an authenticated GET request for a Slack API endpoint
+    fn get(&self, endpoint: &str) -> RequestBuilder {
+        let request = self.client.get(format!("{}/{}", self.base_url, endpoint));
+
+        match &self.auth_header {
+            Some((name, value)) => request.header(name, value),
+            None => request.bearer_auth(&self.api_token),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use super::{dates, Slack};
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    time::Duration,
+};
+
+/// Summary of an export, accumulated as messages are written
+#[derive(Clone, Debug, Serialize)]
+pub struct Stats {
+    messages: u64,
+    skipped_messages: u64,
+    short_messages: u64,
+    messages_without_files: u64,
+    messages_per_day: BTreeMap<NaiveDate, u64>,
+    channels: BTreeMap<String, ChannelStats>,
+    requests: u64,
+    retries: u64,
+    request_latency_p50_ms: Option<u64>,
+    request_latency_p95_ms: Option<u64>,
+    write_ms: u64,
+    deadline_reached: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    last_response_headers: BTreeMap<String, String>,
+    completed_at: Option<i64>,
+    #[serde(skip)]
+    current_channel: Option<String>,
+    #[serde(skip)]
+    history_latencies: Vec<Duration>,
+    #[serde(skip)]
+    write_time: Duration,
+    #[serde(skip)]
+    timezone: Tz,
+}
+
+impl Stats {
+    /// Create an empty summary that buckets messages into days in the provided timezone
+    pub fn new(timezone: Tz) -> Self {
+        Self {
+            messages: 0,
+            skipped_messages: 0,
+            short_messages: 0,
+            messages_without_files: 0,
+            messages_per_day: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            requests: 0,
+            retries: 0,
+            request_latency_p50_ms: None,
+            request_latency_p95_ms: None,
+            write_ms: 0,
+            deadline_reached: false,
+            last_response_headers: BTreeMap::new(),
+            completed_at: None,
+            current_channel: None,
+            history_latencies: Vec::new(),
+            write_time: Duration::ZERO,
+            timezone,
+        }
+    }
+
+    /// Start recording the messages exported from a channel
+    pub fn start_channel(&mut self, channel: &str) {
+        self.channels
+            .insert(channel.into(), ChannelStats::default());
+        self.current_channel = Some(channel.into());
+    }
+
+    /// Record the outcome of the current channel's export
+    pub fn finish_channel(&mut self, error: Option<&anyhow::Error>) {
+        if let Some(channel) = self.current_channel.take() {
+            let channel = self.channels.entry(channel).or_default();
+            channel.ok = error.is_none();
+            channel.error = error.map(ToString::to_string);
+        }
+    }
+
+    /// Record a single exported message
+    pub fn record(&mut self, message: &serde_json::Value) {
+        self.messages += 1;
+
+        if let Some(channel) = &self.current_channel {
+            self.channels.entry(channel.clone()).or_default().messages += 1;
+        }
+
+        let day = message
+            .get("ts")
+            .and_then(serde_json::Value::as_str)
+            .and_then(dates::from_slack_timestamp)
+            .map(|datetime| datetime.with_timezone(&self.timezone).date_naive());
+
+        if let Some(day) = day {
+            *self.messages_per_day.entry(day).or_default() += 1;
+        }
+    }
+
+    /// Record a message that was skipped because it couldn't be written
+    pub fn record_skipped(&mut self) {
+        self.skipped_messages += 1;
+    }
+
+    /// Record messages that were filtered out for having too little text
+    pub fn record_short(&mut self, count: u64) {
+        self.short_messages += count;
+    }
+
+    /// Record messages that were filtered out for not sharing any files
+    pub fn record_without_files(&mut self, count: u64) {
+        self.messages_without_files += count;
+    }
+
+    /// Record the time taken to write a single message to the output
+    pub fn record_write(&mut self, elapsed: Duration) {
+        self.write_time += elapsed;
+    }
+
+    /// Capture a client's counters once it's done making requests
+    pub fn record_requests(&mut self, slack: &Slack) {
+        self.requests += slack.request_count();
+        self.retries += slack.retry_count();
+        self.history_latencies.extend(slack.history_latencies());
+
+        // each channel's client has its own headers, so keep those of the latest one to respond
+        let last_headers = slack.last_headers();
+
+        if !last_headers.is_empty() {
+            self.last_response_headers = last_headers;
+        }
+    }
+
+    /// Record that the export stopped early because its deadline passed
+    pub fn record_deadline(&mut self) {
+        self.deadline_reached = true;
+    }
+
+    /// Mark the export as completed
+    pub fn complete(&mut self) {
+        self.history_latencies.sort();
+
+        self.request_latency_p50_ms = percentile(&self.history_latencies, 50);
+        self.request_latency_p95_ms = percentile(&self.history_latencies, 95);
+        self.write_ms = self.write_time.as_millis() as u64;
+
+        tracing::debug!(
+            p50_ms = self.request_latency_p50_ms,
+            p95_ms = self.request_latency_p95_ms,
+            write_ms = self.write_ms,
+            "Finished export"
+        );
+
+        self.completed_at = Some(Utc::now().timestamp());
+    }
+
+    /// Write the summary as JSON to the provided path
+    pub fn write_to(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+
+    /// Write the summary's counters in the Prometheus text format to the provided path
+    ///
+    /// The file is written in full before being moved into place so that a textfile
+    /// collector never scrapes a partially-written file.
+    pub fn write_metrics_to(&self, path: &Path) -> anyhow::Result<()> {
+        let temporary_path = path.with_extension("prom.tmp");
+        let mut file = File::create(&temporary_path)?;
+
+        write_metric(
+            &mut file,
+            "slump_requests_total",
+            "counter",
+            "Requests made to the Slack API",
+            self.requests,
+        )?;
+        write_metric(
+            &mut file,
+            "slump_retries_total",
+            "counter",
+            "Requests to the Slack API that were retried",
+            self.retries,
+        )?;
+        write_metric(
+            &mut file,
+            "slump_messages_total",
+            "counter",
+            "Messages exported",
+            self.messages,
+        )?;
+
+        if let Some(completed_at) = self.completed_at {
+            write_metric(
+                &mut file,
+                "slump_last_success_timestamp_seconds",
+                "gauge",
+                "Unix timestamp of the last successful export",
+                completed_at,
+            )?;
+        }
+
+        file.sync_all()?;
+        fs::rename(temporary_path, path)?;
+
+        Ok(())
+    }
+}
+
+/// Summary of a single channel's export
+#[derive(Clone, Debug, Default, Serialize)]
+struct ChannelStats {
+    messages: u64,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Find the nearest-rank percentile of sorted durations, in milliseconds
+fn percentile(sorted: &[Duration], percentile: usize) -> Option<u64> {
+    let rank = (sorted.len() * percentile).div_ceil(100).max(1);
+
+    sorted
+        .get(rank - 1)
+        .map(|duration| duration.as_millis() as u64)
+}
+
+/// Write a single metric with its metadata in the Prometheus text format
+fn write_metric(
+    out: &mut impl Write,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl std::fmt::Display,
+) -> std::io::Result<()> {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)?;
+    writeln!(out, "{} {}", name, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Configuration;
+
+    #[test]
+    fn writes_every_metric() {
+        let slack = Slack::try_from(Configuration::new("xoxb-test", "C0123ABCD")).unwrap();
+        let mut stats = Stats::new(Tz::UTC);
+
+        stats.start_channel("C0123ABCD");
+        stats.record(&serde_json::json!({ "ts": "1700000000.000100" }));
+        stats.record_requests(&slack);
+        stats.finish_channel(None);
+        stats.complete();
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("slump.prom");
+        stats.write_metrics_to(&path).unwrap();
+
+        let metrics = fs::read_to_string(path).unwrap();
+
+        for metric in [
+            "slump_requests_total 0",
+            "slump_retries_total 0",
+            "slump_messages_total 1",
+            "slump_last_success_timestamp_seconds ",
+        ] {
+            assert!(
+                metrics.contains(metric),
+                "{} is missing from {}",
+                metric,
+                metrics
+            );
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use flate2::bufread::MultiGzDecoder;
+use serde::de::IgnoredAny;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+/// First bytes of every gzip stream
+static GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// First bytes of every Zstandard frame
+static ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Re-read a written export, checking that it's well-formed JSON (a single document or NDJSON)
+///
+/// Gzipped and Zstandard-compressed exports are decompressed on the fly, and nothing is held in
+/// memory beyond a buffer, so exports of any size can be checked. Returns the number of top-level
+/// JSON values.
+pub fn validate_export(path: &Path) -> anyhow::Result<u64> {
+    let file = File::open(path)
+        .map_err(|error| anyhow::anyhow!("Error opening {}: {}", path.display(), error))?;
+    let mut reader = BufReader::new(file);
+
+    // detect compression from the contents rather than trusting the file's extension
+    let start = reader.fill_buf()?;
+
+    let reader: Box<dyn Read> = if start.starts_with(&GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if start.starts_with(&ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        Box::new(reader)
+    };
+
+    let mut values = 0;
+
+    for value in
+        serde_json::Deserializer::from_reader(BufReader::new(reader)).into_iter::<IgnoredAny>()
+    {
+        value
+            .map_err(|error| anyhow::anyhow!("Invalid export in {}: {}", path.display(), error))?;
+        values += 1;
+    }
+
+    if values == 0 {
+        anyhow::bail!("Invalid export in {}: the file is empty", path.display());
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    static NDJSON: &[u8] = b"{\"ts\":\"3\"}\n{\"ts\":\"2\"}\n{\"ts\":\"1\"}\n";
+
+    /// Write an export to a temporary file
+    fn export(contents: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+
+    /// Compress an export with gzip
+    fn gzip(contents: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn counts_the_values_of_gzipped_exports() {
+        let file = export(&gzip(NDJSON));
+
+        assert_eq!(validate_export(file.path()).unwrap(), 3);
+    }
+
+    #[test]
+    fn counts_the_values_of_zstd_exports() {
+        let file = export(&zstd::encode_all(&b"[{\"ts\":\"2\"},{\"ts\":\"1\"}]"[..], 0).unwrap());
+
+        assert_eq!(validate_export(file.path()).unwrap(), 1);
+    }
+
+    #[test]
+    fn rejects_truncated_gzipped_exports() {
+        let compressed = gzip(b"[{\"ts\":\"2\"},{\"ts\":\"1\"}]");
+        let file = export(&compressed[..compressed.len() / 2]);
+
+        assert!(validate_export(file.path()).is_err());
+    }
+
+    #[test]
+    fn rejects_unterminated_plain_exports() {
+        let file = export(b"[{\"ts\":\"2\"},");
+
+        let error = validate_export(file.path()).unwrap_err();
+
+        assert!(error.to_string().starts_with("Invalid export"), "{}", error);
+    }
+}